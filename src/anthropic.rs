@@ -0,0 +1,87 @@
+use futures::{future::BoxFuture, FutureExt};
+use reqwest::Client;
+
+use crate::{
+    error::ProbeError,
+    provider::{
+        classify_model_list, with_error_buckets, Classification, Endpoint, Provider, Response,
+    },
+    validate::{self, KeyFormat, Length, Malformed},
+};
+
+/// API version sent with every request, see
+/// <https://docs.anthropic.com/en/api/versioning>.
+pub const API_VERSION: &str = "2023-06-01";
+
+const BUCKETS: [&str; 10] = with_error_buckets(["key", "no_balance_keys"]);
+
+pub struct Anthropic {
+    endpoint: Endpoint,
+}
+
+impl Default for Anthropic {
+    fn default() -> Self {
+        Self {
+            endpoint: Endpoint::new("anthropic", "https://api.anthropic.com/v1"),
+        }
+    }
+}
+
+async fn list_models(base_url: &str, key: &str, client: &Client) -> Result<Response, ProbeError> {
+    let resp = client
+        .get(format!("{base_url}/models"))
+        // The list is paged, 20 models by default; 1000 is the most per page.
+        .query(&[("limit", "1000")])
+        .header("x-api-key", key)
+        .header("anthropic-version", API_VERSION)
+        .send()
+        .await?;
+    Ok(Response::read(resp).await?)
+}
+
+impl Provider for Anthropic {
+    fn name(&self) -> &'static str {
+        "anthropic"
+    }
+
+    fn buckets(&self) -> &'static [&'static str] {
+        &BUCKETS
+    }
+
+    fn endpoint_mut(&mut self) -> &mut Endpoint {
+        &mut self.endpoint
+    }
+
+    fn detail_columns(&self) -> &'static [&'static str] {
+        &["organization", "models"]
+    }
+
+    fn validate(&self, key: &str) -> Result<(), Malformed> {
+        KeyFormat {
+            prefix: "sk-ant-",
+            length: Length::AtLeast(40),
+            charset: validate::is_base64url,
+        }
+        .check(key)
+    }
+
+    fn probe<'a>(
+        &'a self,
+        key: &'a str,
+        client: &'a Client,
+    ) -> BoxFuture<'a, Result<Response, ProbeError>> {
+        list_models(&self.endpoint.base_url, key, client).boxed()
+    }
+
+    fn classify(&self, resp: &Response) -> Classification {
+        let classification = classify_model_list(&self.endpoint, resp, self.buckets());
+        match resp
+            .headers
+            .get("anthropic-organization-id")
+            .and_then(|v| v.to_str().ok())
+        {
+            Some(organization) => classification.with_detail("organization", organization),
+            None => classification,
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::Context;
+use reqwest::{Certificate, Client, ClientBuilder, Proxy};
+
+/// HTTP client settings of one provider. Unset fields fall back to the
+/// provider's `Provider::default_client_options`, then to the constants below.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClientOptions {
+    /// Whole request, from connecting to the last byte of the body.
+    pub timeout: Option<Duration>,
+    pub connect_timeout: Option<Duration>,
+    /// Proxy for every request, e.g. `http://proxy.corp:3128`. Without one,
+    /// `HTTPS_PROXY` and friends from the environment apply.
+    pub proxy: Option<String>,
+    /// PEM file with root certificates trusted on top of the built-in ones.
+    pub ca_cert: Option<PathBuf>,
+    pub user_agent: Option<String>,
+}
+
+impl ClientOptions {
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+    pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+    pub const DEFAULT_USER_AGENT: &'static str =
+        concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+    /// Settings from `over` where given, these otherwise.
+    pub fn with_overrides(self, over: &ClientOptions) -> Self {
+        Self {
+            timeout: over.timeout.or(self.timeout),
+            connect_timeout: over.connect_timeout.or(self.connect_timeout),
+            proxy: over.proxy.clone().or(self.proxy),
+            ca_cert: over.ca_cert.clone().or(self.ca_cert),
+            user_agent: over.user_agent.clone().or(self.user_agent),
+        }
+    }
+
+    pub fn build(&self) -> anyhow::Result<Client> {
+        let mut builder = ClientBuilder::new()
+            .http1_title_case_headers()
+            .connect_timeout(
+                self.connect_timeout
+                    .unwrap_or(Self::DEFAULT_CONNECT_TIMEOUT),
+            )
+            .timeout(self.timeout.unwrap_or(Self::DEFAULT_TIMEOUT))
+            .user_agent(
+                self.user_agent
+                    .as_deref()
+                    .unwrap_or(Self::DEFAULT_USER_AGENT),
+            );
+        if let Some(proxy) = &self.proxy {
+            let proxy = Proxy::all(proxy).with_context(|| format!("invalid proxy `{proxy}`"))?;
+            builder = builder.proxy(proxy);
+        }
+        if let Some(path) = &self.ca_cert {
+            let pem = std::fs::read(path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            let certs = Certificate::from_pem_bundle(&pem)
+                .with_context(|| format!("no PEM certificates in {}", path.display()))?;
+            anyhow::ensure!(
+                !certs.is_empty(),
+                "no PEM certificates in {}",
+                path.display()
+            );
+            for cert in certs {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+        Ok(builder.build()?)
+    }
+}
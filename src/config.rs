@@ -0,0 +1,261 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Deserializer};
+
+use crate::{client::ClientOptions, limiter::LimitOverrides, provider::Provider};
+
+/// Settings read from `aicheck.toml`. Everything is optional; command line
+/// arguments take precedence.
+///
+/// ```toml
+/// format = "csv"
+/// output_dir = "results"
+///
+/// [providers.deepseek]
+/// base_url = "http://127.0.0.1:8080"
+/// rps = 2.0
+/// timeout = "15s"
+///
+/// [providers.gemini]
+/// proxy = "http://proxy.corp:3128"
+/// ca_cert = "/etc/ssl/corp-root.pem"
+///
+/// [profile.nightly]
+/// output_dir = "/var/lib/aicheck"
+///
+/// [profile.nightly.providers.gemini]
+/// rps = 0.5
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// `buckets`, `ndjson`, `csv` or `table`.
+    pub format: Option<String>,
+    pub output_dir: Option<PathBuf>,
+    #[serde(default)]
+    pub providers: HashMap<String, ProviderConfig>,
+    /// Named sets of settings laid over the ones above by `--profile`.
+    #[serde(default)]
+    pub profile: HashMap<String, Config>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProviderConfig {
+    pub base_url: Option<String>,
+    pub rps: Option<f64>,
+    pub concurrency: Option<usize>,
+    pub burst: Option<usize>,
+    /// Like `"30s"` or `"1m 30s"`.
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub timeout: Option<Duration>,
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub connect_timeout: Option<Duration>,
+    /// Like `"http://proxy.corp:3128"`.
+    pub proxy: Option<String>,
+    /// PEM file with extra root certificates.
+    pub ca_cert: Option<PathBuf>,
+    pub user_agent: Option<String>,
+    /// Lowest balance counted as valid, see `ProviderRules::valid_balance`.
+    pub valid_balance: Option<f64>,
+}
+
+impl Config {
+    pub const FILE_NAME: &'static str = "aicheck.toml";
+
+    /// Reads `path`, or else the first existing `aicheck.toml` in the working
+    /// directory and `$XDG_CONFIG_HOME/aicheck` (`~/.config/aicheck`).
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Self> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match Self::search_paths().into_iter().find(|path| path.is_file()) {
+                Some(path) => path,
+                None => return Ok(Self::default()),
+            },
+        };
+        tracing::info!("using config {}", path.display());
+        let contents = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        toml::from_str(&contents).with_context(|| format!("failed to parse {}", path.display()))
+    }
+
+    fn search_paths() -> Vec<PathBuf> {
+        let mut paths = vec![PathBuf::from(Self::FILE_NAME)];
+        let config_home = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")));
+        if let Some(dir) = config_home {
+            paths.push(dir.join("aicheck").join(Self::FILE_NAME));
+        }
+        paths
+    }
+
+    /// These settings with the ones of profile `name` laid over them.
+    pub fn with_profile(mut self, name: &str) -> anyhow::Result<Self> {
+        let Some(profile) = self.profile.remove(name) else {
+            let mut names = self.profile.keys().map(String::as_str).collect::<Vec<_>>();
+            names.sort();
+            anyhow::bail!(
+                "no profile `{name}` in the config, found: [{}]",
+                names.join(", ")
+            );
+        };
+        anyhow::ensure!(
+            profile.profile.is_empty(),
+            "profile `{name}` cannot contain profiles"
+        );
+        for (provider, over) in profile.providers {
+            let merged = self
+                .providers
+                .remove(&provider)
+                .unwrap_or_default()
+                .with_overrides(over);
+            self.providers.insert(provider, merged);
+        }
+        Ok(Self {
+            format: profile.format.or(self.format),
+            output_dir: profile.output_dir.or(self.output_dir),
+            ..self
+        })
+    }
+
+    /// Rejects settings for unknown providers, limits the runner cannot use
+    /// and balance thresholds of providers that report no balance.
+    pub fn check(&self, providers: &[Box<dyn Provider>]) -> anyhow::Result<()> {
+        for (name, config) in &self.providers {
+            let Some(provider) = providers.iter().find(|p| p.name() == name) else {
+                let names = providers.iter().map(|p| p.name()).collect::<Vec<_>>();
+                anyhow::bail!(
+                    "unknown provider `{name}` in the config, expected one of {}",
+                    names.join(", ")
+                );
+            };
+            config
+                .check(provider.as_ref())
+                .with_context(|| format!("providers.{name}"))?;
+        }
+        for (name, profile) in &self.profile {
+            profile
+                .check(providers)
+                .with_context(|| format!("profile.{name}"))?;
+        }
+        Ok(())
+    }
+
+    pub fn provider(&self, name: &str) -> ProviderConfig {
+        self.providers.get(name).cloned().unwrap_or_default()
+    }
+}
+
+impl ProviderConfig {
+    fn with_overrides(self, over: ProviderConfig) -> Self {
+        Self {
+            base_url: over.base_url.or(self.base_url),
+            rps: over.rps.or(self.rps),
+            concurrency: over.concurrency.or(self.concurrency),
+            burst: over.burst.or(self.burst),
+            timeout: over.timeout.or(self.timeout),
+            connect_timeout: over.connect_timeout.or(self.connect_timeout),
+            proxy: over.proxy.or(self.proxy),
+            ca_cert: over.ca_cert.or(self.ca_cert),
+            user_agent: over.user_agent.or(self.user_agent),
+            valid_balance: over.valid_balance.or(self.valid_balance),
+        }
+    }
+
+    fn check(&self, provider: &dyn Provider) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.valid_balance.is_none() || !provider.balance_columns().is_empty(),
+            "valid_balance is not supported, {} reports no balance",
+            provider.name()
+        );
+        if let Some(rps) = self.rps {
+            anyhow::ensure!(rps > 0.0 && rps.is_finite(), "rps must be positive");
+        }
+        anyhow::ensure!(
+            self.concurrency != Some(0),
+            "concurrency must be at least 1"
+        );
+        anyhow::ensure!(self.burst != Some(0), "burst must be at least 1");
+        Ok(())
+    }
+
+    pub fn limits(&self) -> LimitOverrides {
+        LimitOverrides {
+            rps: self.rps,
+            concurrency: self.concurrency,
+            burst: self.burst,
+        }
+    }
+
+    pub fn client_options(&self) -> ClientOptions {
+        ClientOptions {
+            timeout: self.timeout,
+            connect_timeout: self.connect_timeout,
+            proxy: self.proxy.clone(),
+            ca_cert: self.ca_cert.clone(),
+            user_agent: self.user_agent.clone(),
+        }
+    }
+}
+
+fn deserialize_duration<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Duration>, D::Error> {
+    let Some(duration) = Option::<String>::deserialize(d)? else {
+        return Ok(None);
+    };
+    humantime::parse_duration(&duration)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+/// Environment variable overriding `provider`'s base URL, e.g.
+/// `AICHECK_DEEPSEEK_BASE_URL`.
+pub fn base_url_var(provider: &str) -> String {
+    format!("AICHECK_{}_BASE_URL", provider.to_uppercase())
+}
+
+/// Points each provider at its configured base URL. `overrides` from the
+/// command line win over the environment, which wins over `config`.
+pub fn apply_base_urls(
+    providers: &mut [Box<dyn Provider>],
+    overrides: &[(String, String)],
+    config: &Config,
+) -> anyhow::Result<()> {
+    for provider in providers {
+        let name = provider.name();
+        let var = base_url_var(name);
+        let base_url = overrides
+            .iter()
+            .rev()
+            .find(|(provider, _)| provider == name)
+            .map(|(_, url)| (url.clone(), "--base-url"))
+            .or_else(|| std::env::var(&var).ok().map(|url| (url, var.as_str())))
+            .or_else(|| {
+                let url = config.providers.get(name)?.base_url.clone()?;
+                Some((url, "config file"))
+            });
+        if let Some((base_url, source)) = base_url {
+            let base_url =
+                parse_base_url(&base_url).with_context(|| format!("{name}: {source}"))?;
+            tracing::info!("{name}: using {base_url} from {source}");
+            provider.set_base_url(base_url);
+        }
+    }
+    Ok(())
+}
+
+/// Checks that `url` is an absolute http(s) URL and drops trailing slashes.
+pub fn parse_base_url(url: &str) -> anyhow::Result<String> {
+    let parsed = reqwest::Url::parse(url).with_context(|| format!("invalid URL `{url}`"))?;
+    anyhow::ensure!(
+        matches!(parsed.scheme(), "http" | "https"),
+        "`{url}` is not an http(s) URL"
+    );
+    Ok(url.trim_end_matches('/').to_string())
+}
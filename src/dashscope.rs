@@ -0,0 +1,82 @@
+use futures::{future::BoxFuture, FutureExt};
+use reqwest::Client;
+use serde_json::Value;
+
+use crate::{
+    error::ProbeError,
+    provider::{
+        classify_model_list, get_with_bearer, with_error_buckets, Classification, Endpoint,
+        Provider, Response,
+    },
+    validate::{KeyFormat, Length, Malformed},
+};
+
+const BUCKETS: [&str; 10] = with_error_buckets(["key", "arrears_keys"]);
+
+pub struct DashScope {
+    endpoint: Endpoint,
+}
+
+impl Default for DashScope {
+    fn default() -> Self {
+        Self {
+            endpoint: Endpoint::new(
+                "dashscope",
+                "https://dashscope.aliyuncs.com/compatible-mode/v1",
+            ),
+        }
+    }
+}
+
+/// The error code of a DashScope answer, like `Arrearage`. The native API
+/// puts it at the top level, the OpenAI-compatible one under `error`.
+fn error_code(body: &str) -> Option<String> {
+    let json = serde_json::from_str::<Value>(body).ok()?;
+    let code = json
+        .pointer("/code")
+        .or_else(|| json.pointer("/error/code"))?;
+    code.as_str().map(String::from)
+}
+
+impl Provider for DashScope {
+    fn name(&self) -> &'static str {
+        "dashscope"
+    }
+
+    fn buckets(&self) -> &'static [&'static str] {
+        &BUCKETS
+    }
+
+    fn endpoint_mut(&mut self) -> &mut Endpoint {
+        &mut self.endpoint
+    }
+
+    fn detail_columns(&self) -> &'static [&'static str] {
+        &["code", "models"]
+    }
+
+    fn validate(&self, key: &str) -> Result<(), Malformed> {
+        KeyFormat {
+            prefix: "sk-",
+            length: Length::Exact(35),
+            charset: |b| b.is_ascii_hexdigit(),
+        }
+        .check(key)
+    }
+
+    fn probe<'a>(
+        &'a self,
+        key: &'a str,
+        client: &'a Client,
+    ) -> BoxFuture<'a, Result<Response, ProbeError>> {
+        get_with_bearer(self.endpoint.url("/models"), key, client).boxed()
+    }
+
+    fn classify(&self, resp: &Response) -> Classification {
+        let classification = classify_model_list(&self.endpoint, resp, self.buckets());
+        match error_code(&resp.text) {
+            Some(code) if resp.status != 200 => classification.with_detail("code", code),
+            _ => classification,
+        }
+    }
+}
@@ -1,13 +1,16 @@
-use std::time::Duration;
-
-use futures::StreamExt;
-use indicatif::ProgressBar;
+use futures::{future::BoxFuture, FutureExt};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use tokio_stream::wrappers::IntervalStream;
-use tracing::instrument;
 
-use crate::save_to_file;
+use crate::{
+    error::ProbeError,
+    provider::{
+        classify_failure, get_with_bearer, with_error_buckets, Classification, Endpoint, Provider,
+        Response,
+    },
+    report::KeyStatus,
+    validate::{KeyFormat, Length, Malformed},
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 struct UserBalance {
@@ -23,86 +26,80 @@ struct BalanceInfo {
     pub topped_up_balance: String,
 }
 
-#[instrument(skip_all)]
-async fn get_balance(key: &str, client: Client) -> anyhow::Result<UserBalance> {
-    let url = "https://api.deepseek.com/user/balance";
-    let resp = client
-        .get(url)
-        .header("Authorization", format!("Bearer {key}"))
-        .send()
-        .await?;
-    if resp.status() != reqwest::StatusCode::OK {
-        let text = resp.text().await?;
-        return Err(anyhow::anyhow!(text));
-    }
-    let user_balance = resp.json::<UserBalance>().await?;
+const BUCKETS: [&str; 10] = with_error_buckets(["key", "no_balance_keys"]);
 
-    Ok(user_balance)
+pub struct Deepseek {
+    endpoint: Endpoint,
 }
 
-#[instrument(skip_all)]
-pub async fn check(keys: Vec<String>, query_per_sec: usize, client: Client) -> anyhow::Result<()> {
-    let bar = ProgressBar::new(keys.len() as u64);
-    let tasks = keys.into_iter().map(|key| {
-        let client = client.clone();
-        let bar = bar.clone();
-        async move {
-            let resp = get_balance(&key, client).await;
-            bar.inc(1);
-            (key, resp)
+impl Default for Deepseek {
+    fn default() -> Self {
+        Self {
+            endpoint: Endpoint::new("deepseek", "https://api.deepseek.com"),
         }
-    });
-    let interval_tick = 1_f64 / (query_per_sec as f64);
-    let interval = tokio::time::interval(Duration::from_secs_f64(interval_tick));
-    let throttled_tasks = IntervalStream::new(interval).zip(futures::stream::iter(tasks));
-    let resp = throttled_tasks
-        .map(|(_, task)| task)
-        .buffer_unordered(query_per_sec)
-        .collect::<Vec<_>>()
-        .await;
-    bar.finish();
-    check_resp(resp).await?;
-
-    Ok(())
+    }
 }
 
-#[instrument(skip_all)]
-async fn check_resp(resp: Vec<(String, anyhow::Result<UserBalance>)>) -> anyhow::Result<()> {
-    let mut total = 0_f64;
-    let mut have_banlance_keys = Vec::new();
-    let mut no_balance_keys = Vec::new();
-    let mut invalid_keys = Vec::new();
-    let mut detail = Vec::new();
-    detail.push("key, total_balance".to_string());
-    for (key, resp) in resp.iter() {
-        match resp {
-            Ok(user) => {
-                let total_balance = user.balance_infos[0]
-                    .total_balance
-                    .parse::<f64>()
-                    .unwrap_or_default();
-                if total_balance > 0_f64 {
-                    have_banlance_keys.push(key);
-                    total += total_balance;
-                } else {
-                    no_balance_keys.push(key);
-                }
-                detail.push(format!("{key}, {total_balance}"));
-            }
-            Err(err) => {
-                tracing::error!("Error: {key}, {err}");
-                invalid_keys.push(key);
-                continue;
-            }
+impl Provider for Deepseek {
+    fn name(&self) -> &'static str {
+        "deepseek"
+    }
+
+    fn buckets(&self) -> &'static [&'static str] {
+        &BUCKETS
+    }
+
+    fn endpoint_mut(&mut self) -> &mut Endpoint {
+        &mut self.endpoint
+    }
+
+    fn balance_columns(&self) -> &'static [&'static str] {
+        &["total_balance", "granted_balance", "topped_up_balance"]
+    }
+
+    fn validate(&self, key: &str) -> Result<(), Malformed> {
+        KeyFormat {
+            prefix: "sk-",
+            length: Length::Exact(35),
+            charset: |b| b.is_ascii_hexdigit(),
         }
+        .check(key)
     }
 
-    let prefix = "deepseek";
-    save_to_file(have_banlance_keys, &format!("{prefix}_key")).await?;
-    save_to_file(no_balance_keys, &format!("{prefix}_no_balance_keys")).await?;
-    save_to_file(invalid_keys, &format!("{prefix}_invalid_keys")).await?;
-    save_to_file(detail, &format!("{prefix}_detail.csv")).await?;
-    tracing::info!("total: {total}");
+    fn probe<'a>(
+        &'a self,
+        key: &'a str,
+        client: &'a Client,
+    ) -> BoxFuture<'a, Result<Response, ProbeError>> {
+        get_with_bearer(self.endpoint.url("/user/balance"), key, client).boxed()
+    }
 
-    Ok(())
+    fn classify(&self, resp: &Response) -> Classification {
+        if let Some(classification) = self.endpoint.classify(resp, self.buckets()) {
+            return classification;
+        }
+        if resp.status != 200 {
+            return classify_failure(resp);
+        }
+        let user = match serde_json::from_str::<UserBalance>(&resp.text) {
+            Ok(user) => user,
+            Err(err) => return Classification::failed(ProbeError::decode(resp.status, err)),
+        };
+        let Some(info) = user.balance_infos.first() else {
+            return Classification::new(KeyStatus::NoBalance, "no_balance_keys");
+        };
+        let currency = Some(info.currency.as_str());
+        let total_balance = info.total_balance.parse::<f64>().unwrap_or_default();
+        let granted_balance = info.granted_balance.parse::<f64>().unwrap_or_default();
+        let topped_up_balance = info.topped_up_balance.parse::<f64>().unwrap_or_default();
+        let classification = if self.endpoint.rules.is_valid_balance(total_balance) {
+            Classification::new(KeyStatus::Valid, "key")
+        } else {
+            Classification::new(KeyStatus::NoBalance, "no_balance_keys")
+        };
+        classification
+            .with_balance("total_balance", total_balance, currency)
+            .with_balance("granted_balance", granted_balance, currency)
+            .with_balance("topped_up_balance", topped_up_balance, currency)
+    }
 }
@@ -0,0 +1,38 @@
+/// Providers to probe, in order, for keys that only carry the generic `sk-`
+/// prefix. Candidates whose `Provider::validate` rejects the key are skipped.
+///
+/// DeepSeek and DashScope keys are both `sk-` and 32 hex digits and cannot be
+/// told apart by format: every such key is sent to DeepSeek first and, when
+/// DeepSeek rejects it, to DashScope.
+pub const SK_CANDIDATES: &[&str] = &["deepseek", "dashscope", "siliconflow", "moonshot", "openai"];
+
+/// Prefixes of OpenAI's project, service account and admin keys.
+pub const OPENAI_PREFIXES: &[&str] = &["sk-proj-", "sk-svcacct-", "sk-admin-"];
+
+/// Names of the providers `key` may belong to, in the order they are tried.
+/// Empty when the key matches no known prefix, nor the `id.secret` shape of
+/// Zhipu keys.
+pub fn candidates(key: &str) -> Vec<&'static str> {
+    if key.starts_with("sk-or-") {
+        return vec!["openrouter"];
+    }
+    if key.starts_with("sk-ant-") {
+        return vec!["anthropic"];
+    }
+    if OPENAI_PREFIXES.iter().any(|prefix| key.starts_with(prefix)) {
+        return vec!["openai"];
+    }
+    if key.starts_with("AIza") {
+        return vec!["gemini"];
+    }
+    if key.starts_with("sk_") {
+        return vec!["ppinfra"];
+    }
+    if key.starts_with("sk-") {
+        return SK_CANDIDATES.to_vec();
+    }
+    if key.contains('.') {
+        return vec!["zhipu"];
+    }
+    Vec::new()
+}
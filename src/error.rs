@@ -0,0 +1,92 @@
+use std::fmt;
+
+use crate::{provider::Response, report::KeyStatus};
+
+/// Why a probe did not produce an answer about the key itself.
+#[derive(Debug)]
+pub enum ProbeError {
+    /// 401: the key was rejected.
+    Unauthorized { status: u16, body: String },
+    /// 403: the key exists but may not use the endpoint.
+    Forbidden { status: u16, body: String },
+    /// 429
+    RateLimited { status: u16, body: String },
+    /// 5xx
+    ServerError { status: u16, body: String },
+    /// Any other non-200 status.
+    Unexpected { status: u16, body: String },
+    /// No response: DNS, TLS, connection or timeout errors.
+    Transport(String),
+    /// A 200 response whose body could not be parsed.
+    Decode { status: u16, error: String },
+}
+
+impl ProbeError {
+    pub fn from_response(resp: &Response) -> Self {
+        let status = resp.status;
+        let body = resp.text.clone();
+        match status {
+            401 => Self::Unauthorized { status, body },
+            403 => Self::Forbidden { status, body },
+            429 => Self::RateLimited { status, body },
+            500.. => Self::ServerError { status, body },
+            _ => Self::Unexpected { status, body },
+        }
+    }
+
+    pub fn decode(status: u16, error: serde_json::Error) -> Self {
+        Self::Decode {
+            status,
+            error: error.to_string(),
+        }
+    }
+
+    pub fn key_status(&self) -> KeyStatus {
+        match self {
+            Self::Unauthorized { .. } => KeyStatus::Invalid,
+            Self::Forbidden { .. } => KeyStatus::Disabled,
+            Self::RateLimited { .. } => KeyStatus::RateLimited,
+            _ => KeyStatus::Unknown,
+        }
+    }
+
+    pub fn bucket(&self) -> &'static str {
+        match self {
+            Self::Unauthorized { .. } => "invalid_keys",
+            Self::Forbidden { .. } => "forbidden_keys",
+            Self::RateLimited { .. } => "429_keys",
+            Self::ServerError { .. } => "server_err_keys",
+            Self::Unexpected { .. } => "http_err_keys",
+            Self::Transport(_) => "failed_keys",
+            Self::Decode { .. } => "decode_err_keys",
+        }
+    }
+}
+
+impl fmt::Display for ProbeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unauthorized { status, body }
+            | Self::Forbidden { status, body }
+            | Self::RateLimited { status, body }
+            | Self::ServerError { status, body }
+            | Self::Unexpected { status, body } => write!(f, "HTTP {status}: {body}"),
+            Self::Transport(err) => f.write_str(err),
+            Self::Decode { status, error } => write!(f, "HTTP {status}, bad body: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for ProbeError {}
+
+impl From<reqwest::Error> for ProbeError {
+    fn from(err: reqwest::Error) -> Self {
+        let mut message = err.to_string();
+        let mut source = std::error::Error::source(&err);
+        while let Some(err) = source {
+            message = format!("{message}: {err}");
+            source = err.source();
+        }
+        Self::Transport(message)
+    }
+}
@@ -1,127 +1,155 @@
-use std::time::Duration;
-
-use futures::StreamExt;
-use indicatif::ProgressBar;
-use reqwest::Client;
+use futures::{future::BoxFuture, FutureExt};
+use reqwest::{header::HeaderValue, Client, RequestBuilder};
 use serde_json::json;
-use tokio_stream::wrappers::IntervalStream;
-use tracing::instrument;
 
-use crate::save_to_file;
+use crate::{
+    client::ClientOptions,
+    error::ProbeError,
+    limiter::Limits,
+    provider::{Classification, Endpoint, Provider, Response},
+    redact::RedactedKey,
+    report::KeyStatus,
+    rules::ProviderRules,
+    validate::{self, KeyFormat, Length, Malformed},
+};
 
-struct GeminiResp {
-    pub status: u16,
-    pub text: String,
+pub struct Gemini {
+    model: Option<String>,
+    endpoint: Endpoint,
 }
 
-async fn say_hi(key: &str, model: &str, client: Client) -> anyhow::Result<GeminiResp> {
-    let url = format!(
-        "https://generativelanguage.googleapis.com/v1beta/models/{model}:generateContent?key={key}"
-    );
-    let resp = client
-        .post(url)
-        .json(&json!({
-            "contents": [{
-                "parts": [{"text": "hi"}]
-            }]
-        }))
+const BUCKETS: &[&str] = &[
+    "key",
+    "429_keys",
+    "invalid_keys",
+    "gemma3_only_keys",
+    "unknow_err_key",
+    "location_err_key",
+    "malformed_keys",
+];
+
+impl Gemini {
+    pub fn new(model: Option<String>) -> Self {
+        Self {
+            model,
+            endpoint: Endpoint::new("gemini", "https://generativelanguage.googleapis.com/v1beta"),
+        }
+    }
+}
+
+/// Authenticates through the `x-goog-api-key` header so the key never ends up
+/// in a URL, and strips the URL from transport errors for good measure.
+async fn send(key: &str, req: RequestBuilder) -> Result<reqwest::Response, ProbeError> {
+    let mut key =
+        HeaderValue::from_str(key).map_err(|err| ProbeError::Transport(err.to_string()))?;
+    key.set_sensitive(true);
+    let resp = req
+        .header("x-goog-api-key", key)
         .send()
-        .await?;
-    let status = resp.status().as_u16();
-    let text = resp.text().await?;
-    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
-    tracing::debug!("{key}, {model}, {}", status);
-    Ok(GeminiResp { status, text })
+        .await
+        .map_err(reqwest::Error::without_url)?;
+    Ok(resp)
 }
 
-async fn list_model(key: &str, client: Client) -> anyhow::Result<GeminiResp> {
-    let url = format!("https://generativelanguage.googleapis.com/v1beta/models?key={key}");
-    let resp = client.get(url).send().await?;
+async fn say_hi(
+    base_url: &str,
+    key: &str,
+    model: &str,
+    client: &Client,
+) -> Result<Response, ProbeError> {
+    let url = format!("{base_url}/models/{model}:generateContent");
+    let req = client.post(url).json(&json!({
+        "contents": [{
+            "parts": [{"text": "hi"}]
+        }]
+    }));
+    let resp = send(key, req).await?;
+    let mut resp = Response::read(resp)
+        .await
+        .map_err(reqwest::Error::without_url)?;
+    resp.text = resp.text.split_whitespace().collect::<Vec<_>>().join(" ");
+    tracing::debug!("{}, {model}, {}", RedactedKey::new(key), resp.status);
+    Ok(resp)
+}
+
+async fn list_model(base_url: &str, key: &str, client: &Client) -> Result<Response, ProbeError> {
+    let resp = send(key, client.get(format!("{base_url}/models"))).await?;
     let status = resp.status().as_u16();
+    let headers = resp.headers().clone();
     let text = "".to_string();
-    Ok(GeminiResp { status, text })
+    Ok(Response {
+        status,
+        headers,
+        text,
+    })
 }
 
-#[instrument(skip_all)]
-pub async fn check(
-    keys: Vec<String>,
-    query_per_sec: usize,
-    client: Client,
-    model: &Option<String>,
-) -> anyhow::Result<()> {
-    let bar = ProgressBar::new(keys.len() as u64);
-    let tasks = keys.into_iter().map(|key| {
-        let client = client.clone();
-        let bar = bar.clone();
-        let model = model.clone();
-        async move {
-            let resp = match model {
-                Some(model) => say_hi(&key, &model, client).await,
-                None => list_model(&key, client).await,
-            };
-            bar.inc(1);
-            (key, resp)
+impl Provider for Gemini {
+    fn name(&self) -> &'static str {
+        "gemini"
+    }
+
+    fn endpoint_mut(&mut self) -> &mut Endpoint {
+        &mut self.endpoint
+    }
+
+    fn buckets(&self) -> &'static [&'static str] {
+        BUCKETS
+    }
+
+    fn error_bucket(&self) -> &'static str {
+        "unknow_err_key"
+    }
+
+    /// Google flags accounts that fire too many generation requests at once.
+    fn default_limits(&self) -> Limits {
+        Limits {
+            rps: 1.0,
+            concurrency: 2,
+            burst: 1,
         }
-    });
-    let interval_tick = 1_f64 / (query_per_sec as f64);
-    let interval = tokio::time::interval(Duration::from_secs_f64(interval_tick));
-    let throttled_tasks = IntervalStream::new(interval).zip(futures::stream::iter(tasks));
-    let resp = throttled_tasks
-        .map(|(_, task)| task)
-        .buffer_unordered(query_per_sec)
-        .collect::<Vec<_>>()
-        .await;
-    bar.finish();
-    check_resp(resp).await?;
-    tracing::info!("done");
-    Ok(())
-}
+    }
+
+    /// Generating even a short reply can take a while.
+    fn default_client_options(&self) -> ClientOptions {
+        ClientOptions {
+            timeout: Some(ClientOptions::DEFAULT_TIMEOUT),
+            connect_timeout: Some(ClientOptions::DEFAULT_CONNECT_TIMEOUT),
+            ..ClientOptions::default()
+        }
+    }
+
+    fn validate(&self, key: &str) -> Result<(), Malformed> {
+        KeyFormat {
+            prefix: "AIza",
+            length: Length::Exact(39),
+            charset: validate::is_base64url,
+        }
+        .check(key)
+    }
 
-#[instrument(skip_all)]
-async fn check_resp(resp: Vec<(String, anyhow::Result<GeminiResp>)>) -> anyhow::Result<()> {
-    let mut have_banlance_keys = Vec::new();
-    let mut ratelimit_keys = Vec::new();
-    let mut invalid_keys = Vec::new();
-    let mut location_err_keys = Vec::new();
-    let mut unknow_error_keys = Vec::new();
-    let mut gemma3_only_keys = Vec::new();
-    let mut detail = Vec::new();
-    detail.push("key, status_code, text".to_string());
-    for (key, resp) in resp.iter() {
-        match resp {
-            Ok(gemini_resp) => {
-                match gemini_resp {
-                    GeminiResp { status: 200, .. } => have_banlance_keys.push(key),
-                    GeminiResp { status: 403, text} if text.contains("reported as leaked")  => gemma3_only_keys.push(key),
-                    GeminiResp { status: 403, text} if !text.contains("PERMISSION_DENIED") => location_err_keys.push(key),
-                    GeminiResp { status: 400, text} if text.contains("location is not supported") => location_err_keys.push(key),
-                    GeminiResp { status: 429, text } if text.contains("Quota exceeded for quota metric 'Generate Content API requests per minute'") => invalid_keys.push(key),
-                    GeminiResp { status: 403, text} if text.contains("PERMISSION_DENIED")  => invalid_keys.push(key),
-                    GeminiResp { status: 400, ..} |
-                    GeminiResp { status: 401, ..} => invalid_keys.push(key),
-                    GeminiResp { status: 429, ..} => ratelimit_keys.push(key),
-                    _ => unknow_error_keys.push(key),
-                };
-                detail.push(format!(
-                    "{key}, {}, {}",
-                    gemini_resp.status, gemini_resp.text
-                ));
-            }
-            Err(err) => {
-                tracing::error!("Error: {key}, {err}");
-                unknow_error_keys.push(key);
-                continue;
-            }
+    fn probe<'a>(
+        &'a self,
+        key: &'a str,
+        client: &'a Client,
+    ) -> BoxFuture<'a, Result<Response, ProbeError>> {
+        match &self.model {
+            Some(model) => say_hi(&self.endpoint.base_url, key, model, client).boxed(),
+            None => list_model(&self.endpoint.base_url, key, client).boxed(),
         }
     }
 
-    let prefix = "gemini";
-    save_to_file(have_banlance_keys, &format!("{prefix}_key")).await?;
-    save_to_file(ratelimit_keys, &format!("{prefix}_429_keys")).await?;
-    save_to_file(invalid_keys, &format!("{prefix}_invalid_keys")).await?;
-    save_to_file(gemma3_only_keys, &format!("{prefix}_gemma3_only_keys")).await?;
-    save_to_file(unknow_error_keys, &format!("{prefix}_unknow_err_key")).await?;
-    save_to_file(location_err_keys, &format!("{prefix}_location_err_key")).await?;
-    save_to_file(detail, &format!("{prefix}_detail.csv")).await?;
-    Ok(())
+    fn classify(&self, resp: &Response) -> Classification {
+        self.endpoint.classify(resp, BUCKETS).unwrap_or_else(|| {
+            Classification::new(KeyStatus::Unknown, self.error_bucket())
+                .with_error(resp.text.clone())
+        })
+    }
+}
+
+/// Classifies a Gemini answer with the built-in rules from `rules.toml`.
+pub fn classify(status: u16, body: &str) -> Classification {
+    ProviderRules::builtin("gemini")
+        .classify(status, body, BUCKETS)
+        .unwrap_or_else(|| Classification::new(KeyStatus::Unknown, "unknow_err_key"))
 }
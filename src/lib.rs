@@ -1,22 +1,36 @@
-use std::ops::Deref;
-
+pub mod anthropic;
+pub mod client;
+pub mod config;
+pub mod dashscope;
 pub mod deepseek;
+pub mod detect;
+pub mod error;
 pub mod gemini;
+pub mod limiter;
+pub mod moonshot;
+pub mod openai;
 pub mod openrouter;
+pub mod output;
 pub mod ppinfra;
+pub mod provider;
+pub mod redact;
+pub mod report;
+pub mod retry;
+pub mod rules;
+pub mod runner;
 pub mod siliconflow;
+pub mod validate;
+pub mod zhipu;
 
-pub async fn save_to_file<I, S>(keys: I, filename: &str) -> anyhow::Result<()>
-where
-    I: Deref<Target = [S]>,
-    S: AsRef<str>,
-{
-    if keys.is_empty() {
-        return Ok(());
-    }
-    let all_key = keys.iter().map(|k| k.as_ref()).collect::<Vec<&str>>();
-    let mut all_key = all_key.join("\n");
-    all_key.push('\n');
-    tokio::fs::write(filename, all_key.as_bytes()).await?;
-    Ok(())
-}
+pub use client::ClientOptions;
+pub use error::ProbeError;
+pub use limiter::{LimitOverrides, Limits, RateLimiter};
+pub use output::{
+    save_reports, BucketSink, CsvSink, NdjsonSink, RedactingSink, ReportSink, TableSink,
+};
+pub use provider::Provider;
+pub use redact::RedactedKey;
+pub use report::{Detail, KeyReport, KeyStatus};
+pub use retry::RetryPolicy;
+pub use runner::{check, check_auto_stream, check_stream, RunOptions};
+pub use validate::parse_keys;
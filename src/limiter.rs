@@ -0,0 +1,143 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    /// Sustained requests per second.
+    pub rps: f64,
+    /// Requests in flight at once.
+    pub concurrency: usize,
+    /// Requests allowed back to back before `rps` kicks in.
+    pub burst: usize,
+}
+
+impl Limits {
+    pub fn with_overrides(self, overrides: &LimitOverrides) -> Self {
+        Self {
+            rps: overrides.rps.unwrap_or(self.rps),
+            concurrency: overrides.concurrency.unwrap_or(self.concurrency),
+            burst: overrides.burst.unwrap_or(self.burst),
+        }
+    }
+}
+
+/// User-supplied settings that replace a provider's `Provider::default_limits`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LimitOverrides {
+    pub rps: Option<f64>,
+    pub concurrency: Option<usize>,
+    pub burst: Option<usize>,
+}
+
+/// Token bucket shared by every request to one provider.
+///
+/// Each `acquire` takes a token immediately, letting the balance go negative,
+/// and sleeps until the bucket has refilled past it. Callers are therefore
+/// released in the order they arrived without polling.
+///
+/// The rate adapts to the provider's answers: once the recent share of 429s
+/// passes `SLOW_DOWN_SHARE` it is halved, and every answer that is not a 429
+/// wins part of it back until the configured rate is reached again.
+pub struct RateLimiter {
+    state: Mutex<Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    rate: f64,
+    max_rate: f64,
+    burst: f64,
+    last: Instant,
+    /// Moving average of how many answers were 429s.
+    limited_share: f64,
+}
+
+/// Weight of the latest answer in `Bucket::limited_share`.
+const SHARE_WEIGHT: f64 = 0.1;
+const SLOW_DOWN_SHARE: f64 = 0.2;
+/// Lowest rate as a fraction of the configured one.
+const MIN_RATE_FACTOR: f64 = 1.0 / 32.0;
+/// Rate regained per answer that is not a 429, as a fraction of the configured one.
+const SPEED_UP_STEP: f64 = 0.02;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RateChange {
+    Slowed(f64),
+    Restored(f64),
+}
+
+impl Bucket {
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.last = now;
+    }
+}
+
+impl RateLimiter {
+    /// # Panics
+    ///
+    /// If `limits.rps` is not a positive, finite number.
+    pub fn new(limits: &Limits) -> Self {
+        assert!(
+            limits.rps > 0.0 && limits.rps.is_finite(),
+            "rps must be a positive number, got {}",
+            limits.rps
+        );
+        let burst = limits.burst.max(1) as f64;
+        Self {
+            state: Mutex::new(Bucket {
+                tokens: burst,
+                rate: limits.rps,
+                max_rate: limits.rps,
+                burst,
+                last: Instant::now(),
+                limited_share: 0.0,
+            }),
+        }
+    }
+
+    pub async fn acquire(&self) {
+        let wait = {
+            let mut bucket = self.state.lock().unwrap();
+            bucket.refill();
+            bucket.tokens -= 1_f64;
+            match bucket.tokens {
+                0.0.. => Duration::ZERO,
+                _ => Duration::from_secs_f64(-bucket.tokens / bucket.rate),
+            }
+        };
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Feeds one answer into the adaptive rate. Returns the new rate when it
+    /// was just lowered or has just recovered fully.
+    pub fn record(&self, rate_limited: bool) -> Option<RateChange> {
+        let mut bucket = self.state.lock().unwrap();
+        bucket.refill();
+        let sample = if rate_limited { 1.0 } else { 0.0 };
+        bucket.limited_share += (sample - bucket.limited_share) * SHARE_WEIGHT;
+        if bucket.limited_share > SLOW_DOWN_SHARE {
+            let floor = bucket.max_rate * MIN_RATE_FACTOR;
+            let rate = (bucket.rate / 2.0).max(floor);
+            // Answers already in flight were sent at the old rate; start over
+            // so they do not halve it again.
+            bucket.limited_share = 0.0;
+            if rate < bucket.rate {
+                bucket.rate = rate;
+                return Some(RateChange::Slowed(rate));
+            }
+        } else if !rate_limited && bucket.rate < bucket.max_rate {
+            bucket.rate = (bucket.rate + bucket.max_rate * SPEED_UP_STEP).min(bucket.max_rate);
+            if bucket.rate == bucket.max_rate {
+                return Some(RateChange::Restored(bucket.rate));
+            }
+        }
+        None
+    }
+}
@@ -1,13 +1,34 @@
-use std::{path::PathBuf, time::Duration};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
 
 use aicheck::{
-    deepseek,
-    gemini::check,
-    openrouter, ppinfra,
-    siliconflow::{self},
+    anthropic::Anthropic,
+    check_auto_stream, check_stream,
+    config::{apply_base_urls, parse_base_url, Config},
+    dashscope::DashScope,
+    deepseek::Deepseek,
+    gemini::Gemini,
+    moonshot::Moonshot,
+    openai::OpenAi,
+    openrouter::OpenRouter,
+    output::{combined_columns, create_run_folder},
+    parse_keys,
+    ppinfra::Ppinfra,
+    rules::{apply_rules, Rules},
+    runner::{duplicate_report, AUTO_BUCKETS, INPUT_BUCKETS},
+    siliconflow::Siliconflow,
+    zhipu::Zhipu,
+    BucketSink, ClientOptions, CsvSink, LimitOverrides, NdjsonSink, Provider, RedactingSink,
+    ReportSink, RetryPolicy, RunOptions, TableSink,
 };
-use clap::{Parser, Subcommand};
-use reqwest::ClientBuilder;
+use anyhow::Context;
+use clap::{Parser, Subcommand, ValueEnum};
+use futures::StreamExt;
+use indicatif::ProgressBar;
+use tokio::io::AsyncWrite;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 #[derive(Parser, Debug)]
@@ -15,12 +36,79 @@ use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 struct Cli {
     #[arg(value_parser = check_file_exists)]
     keys_file: PathBuf,
+    /// Requests per second to each provider [default: per provider]
+    #[arg(short = 'q', long = "rps", visible_alias = "query-per-sec", value_parser = parse_rps)]
+    rps: Option<f64>,
+    /// Requests in flight at once [default: per provider]
+    #[arg(short, long, value_parser = clap::value_parser!(u16).range(1..))]
+    concurrency: Option<u16>,
+    /// Requests sent back to back before --rps applies [default: per provider]
+    #[arg(short, long, value_parser = clap::value_parser!(u16).range(1..))]
+    burst: Option<u16>,
+    /// Tries per key on timeouts, 429 and 5xx answers; 1 disables retrying
+    #[arg(long, default_value_t = RetryPolicy::default().max_attempts, value_parser = clap::value_parser!(u32).range(1..))]
+    max_attempts: u32,
+    /// Times a rate-limited key is queued again after the others
+    #[arg(long, default_value_t = RunOptions::default().requeues)]
+    max_requeues: u32,
+    /// [default: buckets]
+    #[arg(short, long, value_enum)]
+    format: Option<Format>,
+    /// Write ndjson/csv output to this file instead of stdout, replacing it
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Directory for bucket files [default: .]
+    #[arg(short = 'd', long)]
+    output_dir: Option<PathBuf>,
+    /// Put bucket files in a new timestamped folder inside the output directory
+    #[arg(short, long)]
+    timestamped: bool,
+    /// Print full keys on stdout instead of masking them
+    #[arg(long)]
+    show_keys: bool,
+    /// Send a provider's requests to another host, e.g. `deepseek=http://127.0.0.1:8080`
+    #[arg(long, value_name = "PROVIDER=URL", value_parser = parse_base_url_arg)]
+    base_url: Vec<(String, String)>,
+    /// Give up on a request after this long, e.g. `30s` [default: 15s, 60s for gemini and zhipu]
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    timeout: Option<Duration>,
+    /// Give up on connecting after this long [default: 5s, 10s for gemini and zhipu]
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    connect_timeout: Option<Duration>,
+    /// Send every request through this proxy [default: $HTTPS_PROXY]
+    #[arg(long, value_name = "URL")]
+    proxy: Option<String>,
+    /// Also trust the root certificates in this PEM file
+    #[arg(long, value_name = "PEM", value_parser = check_file_exists)]
+    ca_cert: Option<PathBuf>,
+    /// User-Agent header of every request [default: aicheck/<version>]
+    #[arg(long)]
+    user_agent: Option<String>,
+    /// Config file [default: aicheck.toml in the working directory or ~/.config/aicheck]
+    #[arg(long, value_parser = check_file_exists)]
+    config: Option<PathBuf>,
+    /// Apply the settings of `[profile.<PROFILE>]` from the config file
     #[arg(short, long)]
-    query_per_sec: Option<usize>,
+    profile: Option<String>,
+    /// Classification rules tried before the built-in ones
+    #[arg(long, value_parser = check_file_exists)]
+    rules: Option<PathBuf>,
     #[command(subcommand)]
     command: Commands,
 }
 
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Format {
+    /// One file per bucket, e.g. `deepseek_key`, plus `*_detail.csv`
+    Buckets,
+    /// One JSON object per key
+    Ndjson,
+    /// One CSV row per key
+    Csv,
+    /// Aligned table on stdout
+    Table,
+}
+
 #[non_exhaustive]
 #[derive(Debug, Subcommand)]
 enum Commands {
@@ -32,6 +120,19 @@ enum Commands {
     },
     Ppinfra,
     OpenRouter,
+    #[command(name = "openai")]
+    OpenAi,
+    Anthropic,
+    Moonshot,
+    Zhipu,
+    #[command(name = "dashscope")]
+    DashScope,
+    /// Detect the provider of every key and check each one there
+    Auto {
+        /// Gemini model to test, like `gemini --model`
+        #[arg(short, long)]
+        model: Option<String>,
+    },
 }
 
 fn check_file_exists(file: &str) -> Result<PathBuf, String> {
@@ -43,6 +144,74 @@ fn check_file_exists(file: &str) -> Result<PathBuf, String> {
     }
 }
 
+fn parse_rps(rps: &str) -> Result<f64, String> {
+    match rps.parse::<f64>() {
+        Ok(rps) if rps > 0_f64 && rps.is_finite() => Ok(rps),
+        _ => Err(format!("{rps} is not a positive number")),
+    }
+}
+
+fn parse_base_url_arg(arg: &str) -> Result<(String, String), String> {
+    let (name, url) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("expected PROVIDER=URL, got `{arg}`"))?;
+    let names = all_providers(None)
+        .iter()
+        .map(|p| p.name())
+        .collect::<Vec<_>>();
+    if !names.contains(&name) {
+        return Err(format!(
+            "unknown provider `{name}`, expected one of {}",
+            names.join(", ")
+        ));
+    }
+    let url = parse_base_url(url).map_err(|err| err.to_string())?;
+    Ok((name.to_string(), url))
+}
+
+fn all_providers(model: Option<String>) -> Vec<Box<dyn Provider>> {
+    vec![
+        Box::new(Deepseek::default()),
+        Box::new(Siliconflow::default()),
+        Box::new(OpenRouter::default()),
+        Box::new(Ppinfra::default()),
+        Box::new(Gemini::new(model)),
+        Box::new(OpenAi::default()),
+        Box::new(Anthropic::default()),
+        Box::new(Moonshot::default()),
+        Box::new(Zhipu::default()),
+        Box::new(DashScope::default()),
+    ]
+}
+
+async fn open_output(path: &Option<PathBuf>) -> anyhow::Result<Box<dyn AsyncWrite + Unpin + Send>> {
+    match path {
+        Some(path) => {
+            if tokio::fs::try_exists(path).await? {
+                tracing::warn!("overwriting {}", path.display());
+            }
+            Ok(Box::new(tokio::fs::File::create(path).await?))
+        }
+        None => Ok(Box::new(tokio::io::stdout())),
+    }
+}
+
+/// Resolves on Ctrl-C, or on SIGTERM where there is one, so both save the
+/// results checked so far.
+async fn interrupted() -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result,
+            _ = terminate.recv() => Ok(()),
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     tracing_subscriber::registry()
@@ -50,32 +219,185 @@ async fn main() -> anyhow::Result<()> {
             tracing_subscriber::EnvFilter::try_from_default_env()
                 .unwrap_or_else(|_| format!("{}=info", env!("CARGO_CRATE_NAME")).into()),
         )
-        .with(tracing_subscriber::fmt::layer())
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
         .init();
     let cli = Cli::parse();
     let contents = tokio::fs::read_to_string(&cli.keys_file).await?;
-    let query_per_sec = cli.query_per_sec.unwrap_or(3);
-    let client = ClientBuilder::new()
-        .http1_title_case_headers()
-        .connect_timeout(Duration::from_secs(10))
-        .timeout(Duration::from_secs(60))
-        .pool_max_idle_per_host(query_per_sec)
-        .build()?;
+    let mut config = Config::load(cli.config.as_deref())?;
+    let known = all_providers(None);
+    config.check(&known)?;
+    if let Some(profile) = &cli.profile {
+        config = config.with_profile(profile)?;
+    }
+    let format = match (cli.format, &config.format) {
+        (Some(format), _) => format,
+        (None, Some(format)) => Format::from_str(format, true)
+            .map_err(|err| anyhow::anyhow!("config format `{format}`: {err}"))?,
+        (None, None) => Format::Buckets,
+    };
+    anyhow::ensure!(
+        cli.output.is_none() || matches!(format, Format::Ndjson | Format::Csv),
+        "--output only applies to ndjson and csv output"
+    );
+    anyhow::ensure!(
+        !cli.timestamped || matches!(format, Format::Buckets),
+        "--timestamped only applies to bucket output"
+    );
+    let output_dir = cli
+        .output_dir
+        .clone()
+        .or_else(|| config.output_dir.clone())
+        .unwrap_or_else(|| PathBuf::from("."));
     tracing::info!("正在检查...");
-    let keys = contents
-        .lines()
-        .map(|k| k.trim())
-        .filter(|k| !k.is_empty())
-        .filter(|k| !k.starts_with("#"))
-        .map(String::from)
-        .collect::<Vec<_>>();
-    match &cli.command {
-        Commands::Siliconflow => siliconflow::check(keys, query_per_sec, client).await?,
-        Commands::Deepseek => deepseek::check(keys, query_per_sec, client).await?,
-        Commands::Gemini { model } => check(keys, query_per_sec, client, model).await?,
-        Commands::Ppinfra => ppinfra::check(keys, query_per_sec, client).await?,
-        Commands::OpenRouter => openrouter::check(keys, query_per_sec, client).await?,
+    let (keys, duplicates) = parse_keys(&contents);
+    if !duplicates.is_empty() {
+        tracing::warn!(
+            "{} keys appear more than once, checking each once",
+            duplicates.len()
+        );
+    }
+    let mut providers: Vec<Box<dyn Provider>> = match &cli.command {
+        Commands::Siliconflow => vec![Box::new(Siliconflow::default())],
+        Commands::Deepseek => vec![Box::new(Deepseek::default())],
+        Commands::Gemini { model } => vec![Box::new(Gemini::new(model.clone()))],
+        Commands::Ppinfra => vec![Box::new(Ppinfra::default())],
+        Commands::OpenRouter => vec![Box::new(OpenRouter::default())],
+        Commands::OpenAi => vec![Box::new(OpenAi::default())],
+        Commands::Anthropic => vec![Box::new(Anthropic::default())],
+        Commands::Moonshot => vec![Box::new(Moonshot::default())],
+        Commands::Zhipu => vec![Box::new(Zhipu::default())],
+        Commands::DashScope => vec![Box::new(DashScope::default())],
+        Commands::Auto { model } => all_providers(model.clone()),
+    };
+    apply_base_urls(&mut providers, &cli.base_url, &config)?;
+    let mut rules = match &cli.rules {
+        Some(path) => {
+            let rules = Rules::load(path)?;
+            rules
+                .check(&known)
+                .with_context(|| path.display().to_string())?;
+            rules
+        }
+        None => Rules::default(),
+    };
+    for (name, provider) in &config.providers {
+        if let Some(valid_balance) = provider.valid_balance {
+            rules = rules.or_valid_balance(name, valid_balance);
+        }
+    }
+    apply_rules(&mut providers, &rules)?;
+    let options = RunOptions {
+        limits: LimitOverrides {
+            rps: cli.rps,
+            concurrency: cli.concurrency.map(usize::from),
+            burst: cli.burst.map(usize::from),
+        },
+        provider_limits: config
+            .providers
+            .iter()
+            .map(|(name, provider)| (name.clone(), provider.limits()))
+            .collect(),
+        retry: RetryPolicy {
+            max_attempts: cli.max_attempts,
+            ..Default::default()
+        },
+        requeues: cli.max_requeues,
+    };
+    let client_options = ClientOptions {
+        timeout: cli.timeout,
+        connect_timeout: cli.connect_timeout,
+        proxy: cli.proxy.clone(),
+        ca_cert: cli.ca_cert.clone(),
+        user_agent: cli.user_agent.clone(),
     };
+    let mut clients = HashMap::new();
+    for provider in &providers {
+        let name = provider.name();
+        let client = provider
+            .default_client_options()
+            .with_overrides(&config.provider(name).client_options())
+            .with_overrides(&client_options)
+            .build()
+            .with_context(|| format!("{name} HTTP client"))?;
+        clients.insert(name, client);
+    }
+    let auto = matches!(cli.command, Commands::Auto { .. });
+    let output_dir = match (cli.timestamped, format) {
+        (true, Format::Buckets) => create_run_folder(&output_dir, SystemTime::now()).await?,
+        _ => output_dir,
+    };
+    let mut sink: Box<dyn ReportSink + '_> = match format {
+        Format::Buckets => {
+            let providers = providers.iter().map(|p| p.as_ref());
+            let mut sink = match auto {
+                true => BucketSink::create_combined(providers, "auto", &output_dir).await?,
+                false => BucketSink::create(providers, &output_dir).await?,
+            };
+            sink.add_buckets("input", INPUT_BUCKETS).await?;
+            if auto {
+                sink.add_buckets("auto", AUTO_BUCKETS).await?;
+            }
+            Box::new(sink)
+        }
+        Format::Ndjson => Box::new(NdjsonSink::new(open_output(&cli.output).await?)),
+        Format::Csv => {
+            let writer = open_output(&cli.output).await?;
+            let (columns, details) = combined_columns(providers.iter().map(|p| p.as_ref()));
+            let csv = CsvSink::new(writer, &columns).with_detail_columns(&details);
+            match auto {
+                true => Box::new(csv.with_provider_column()),
+                false => Box::new(csv),
+            }
+        }
+        Format::Table => Box::new(TableSink::new()),
+    };
+    let to_stdout = match format {
+        Format::Buckets => false,
+        Format::Ndjson | Format::Csv => cli.output.is_none(),
+        Format::Table => true,
+    };
+    if to_stdout && !cli.show_keys {
+        sink = Box::new(RedactingSink::new(sink));
+    }
+    let bar = match format {
+        Format::Table => ProgressBar::hidden(),
+        _ => ProgressBar::new(keys.len() as u64),
+    };
+    let reports = match auto {
+        true => check_auto_stream(&providers, keys, &options, clients).boxed(),
+        false => {
+            let client = clients.remove(providers[0].name()).unwrap_or_default();
+            check_stream(providers[0].as_ref(), keys, &options, client).boxed()
+        }
+    };
+    for key in duplicates {
+        sink.write(&duplicate_report(key)).await?;
+    }
+    let interrupted = interrupted();
+    tokio::pin!(reports, interrupted);
+    loop {
+        tokio::select! {
+            report = reports.next() => {
+                let Some(report) = report else {
+                    break;
+                };
+                bar.inc(1);
+                sink.write(&report).await?;
+            }
+            _ = &mut interrupted => {
+                bar.abandon();
+                tracing::warn!("interrupted, results checked so far are saved");
+                sink.finish().await?;
+                return Ok(());
+            }
+        }
+    }
+    bar.finish();
+    sink.finish().await?;
+    if let Format::Buckets = format {
+        tracing::info!("results saved to {}", output_dir.display());
+    }
+    tracing::info!("done");
 
     Ok(())
 }
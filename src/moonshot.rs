@@ -0,0 +1,101 @@
+use futures::{future::BoxFuture, FutureExt};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::ProbeError,
+    provider::{
+        classify_failure, get_with_bearer, with_error_buckets, Classification, Endpoint, Provider,
+        Response,
+    },
+    report::KeyStatus,
+    validate::{KeyFormat, Length, Malformed},
+};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct UserBalance {
+    data: Data,
+}
+
+/// `available_balance` is `voucher_balance` plus `cash_balance`; the cash part
+/// goes negative once usage outruns it.
+#[derive(Debug, Serialize, Deserialize)]
+struct Data {
+    available_balance: f64,
+    voucher_balance: f64,
+    cash_balance: f64,
+}
+
+const BUCKETS: [&str; 11] = with_error_buckets(["key", "no_balance_keys", "neg_balance_keys"]);
+
+pub struct Moonshot {
+    endpoint: Endpoint,
+}
+
+impl Default for Moonshot {
+    fn default() -> Self {
+        Self {
+            endpoint: Endpoint::new("moonshot", "https://api.moonshot.cn/v1"),
+        }
+    }
+}
+
+impl Provider for Moonshot {
+    fn name(&self) -> &'static str {
+        "moonshot"
+    }
+
+    fn buckets(&self) -> &'static [&'static str] {
+        &BUCKETS
+    }
+
+    fn endpoint_mut(&mut self) -> &mut Endpoint {
+        &mut self.endpoint
+    }
+
+    fn balance_columns(&self) -> &'static [&'static str] {
+        &["available_balance", "voucher_balance", "cash_balance"]
+    }
+
+    fn validate(&self, key: &str) -> Result<(), Malformed> {
+        KeyFormat {
+            prefix: "sk-",
+            length: Length::Exact(51),
+            charset: |b| b.is_ascii_alphanumeric(),
+        }
+        .check(key)
+    }
+
+    fn probe<'a>(
+        &'a self,
+        key: &'a str,
+        client: &'a Client,
+    ) -> BoxFuture<'a, Result<Response, ProbeError>> {
+        get_with_bearer(self.endpoint.url("/users/me/balance"), key, client).boxed()
+    }
+
+    fn classify(&self, resp: &Response) -> Classification {
+        if let Some(classification) = self.endpoint.classify(resp, self.buckets()) {
+            return classification;
+        }
+        if resp.status != 200 {
+            return classify_failure(resp);
+        }
+        let balance = match serde_json::from_str::<UserBalance>(&resp.text) {
+            Ok(balance) => balance.data,
+            Err(err) => return Classification::failed(ProbeError::decode(resp.status, err)),
+        };
+        let available = balance.available_balance;
+        let classification = match available {
+            _ if self.endpoint.rules.is_valid_balance(available) => {
+                Classification::new(KeyStatus::Valid, "key")
+            }
+            0.0.. => Classification::new(KeyStatus::NoBalance, "no_balance_keys"),
+            _ => Classification::new(KeyStatus::NegativeBalance, "neg_balance_keys"),
+        };
+        classification
+            .with_balance("available_balance", available, Some("CNY"))
+            .with_balance("voucher_balance", balance.voucher_balance, Some("CNY"))
+            .with_balance("cash_balance", balance.cash_balance, Some("CNY"))
+    }
+}
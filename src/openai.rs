@@ -0,0 +1,79 @@
+use futures::{future::BoxFuture, FutureExt};
+use reqwest::Client;
+
+use crate::{
+    error::ProbeError,
+    provider::{
+        classify_model_list, get_with_bearer, with_error_buckets, Classification, Endpoint,
+        Provider, Response,
+    },
+    validate::{self, KeyFormat, Length, Malformed},
+};
+
+/// Response headers naming the key's owner, recorded as details of the same
+/// name without the prefix.
+const OWNER_HEADERS: [(&str, &str); 2] = [
+    ("openai-organization", "organization"),
+    ("openai-project", "project"),
+];
+
+const BUCKETS: [&str; 10] = with_error_buckets(["key", "no_balance_keys"]);
+
+pub struct OpenAi {
+    endpoint: Endpoint,
+}
+
+impl Default for OpenAi {
+    fn default() -> Self {
+        Self {
+            endpoint: Endpoint::new("openai", "https://api.openai.com/v1"),
+        }
+    }
+}
+
+impl Provider for OpenAi {
+    fn name(&self) -> &'static str {
+        "openai"
+    }
+
+    fn buckets(&self) -> &'static [&'static str] {
+        &BUCKETS
+    }
+
+    fn endpoint_mut(&mut self) -> &mut Endpoint {
+        &mut self.endpoint
+    }
+
+    fn detail_columns(&self) -> &'static [&'static str] {
+        &["organization", "project", "models"]
+    }
+
+    /// Legacy keys are `sk-` and 48 alphanumerics; project, service account
+    /// and admin keys are longer and also contain `-` and `_`.
+    fn validate(&self, key: &str) -> Result<(), Malformed> {
+        KeyFormat {
+            prefix: "sk-",
+            length: Length::AtLeast(51),
+            charset: validate::is_base64url,
+        }
+        .check(key)
+    }
+
+    fn probe<'a>(
+        &'a self,
+        key: &'a str,
+        client: &'a Client,
+    ) -> BoxFuture<'a, Result<Response, ProbeError>> {
+        get_with_bearer(self.endpoint.url("/models"), key, client).boxed()
+    }
+
+    fn classify(&self, resp: &Response) -> Classification {
+        let mut classification = classify_model_list(&self.endpoint, resp, self.buckets());
+        for (header, name) in OWNER_HEADERS {
+            if let Some(value) = resp.headers.get(header).and_then(|v| v.to_str().ok()) {
+                classification = classification.with_detail(name, value);
+            }
+        }
+        classification
+    }
+}
@@ -0,0 +1,604 @@
+use std::{
+    borrow::Cow,
+    collections::{hash_map::Entry, HashMap},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::Context;
+use futures::{future::BoxFuture, FutureExt};
+use tokio::{
+    fs::File,
+    io::{AsyncWrite, AsyncWriteExt},
+};
+
+use crate::{report::format_rfc3339, KeyReport, KeyStatus, Provider, RedactedKey};
+
+pub trait ReportSink: Send {
+    fn write<'a>(&'a mut self, report: &'a KeyReport) -> BoxFuture<'a, anyhow::Result<()>>;
+
+    fn finish(&mut self) -> BoxFuture<'_, anyhow::Result<()>>;
+}
+
+impl<S> ReportSink for Box<S>
+where
+    S: ReportSink + ?Sized,
+{
+    fn write<'a>(&'a mut self, report: &'a KeyReport) -> BoxFuture<'a, anyhow::Result<()>> {
+        (**self).write(report)
+    }
+
+    fn finish(&mut self) -> BoxFuture<'_, anyhow::Result<()>> {
+        (**self).finish()
+    }
+}
+
+/// Writes one file per bucket plus `*_detail.csv` for every provider into
+/// `dir`.
+///
+/// Every file is written to a hidden temp file first and renamed into place by
+/// `finish`, so a previous run is never half-overwritten. A sink dropped
+/// without `finish`, e.g. after an error, removes its temp files and leaves
+/// the previous results as they were. Buckets without keys still produce an
+/// empty file.
+pub struct BucketSink {
+    dir: PathBuf,
+    files: HashMap<(&'static str, &'static str), File>,
+    details: HashMap<&'static str, CsvSink<File>>,
+    pending: Vec<(PathBuf, PathBuf)>,
+    totals: Vec<(&'static str, &'static str, f64)>,
+    /// Buckets of providers whose files are only created with their first
+    /// report.
+    deferred: HashMap<&'static str, &'static [&'static str]>,
+    /// Name of the one `*_detail.csv` taking every report, if any.
+    combined: Option<&'static str>,
+}
+
+impl BucketSink {
+    pub async fn create<'p, I>(providers: I, dir: &Path) -> anyhow::Result<Self>
+    where
+        I: IntoIterator<Item = &'p dyn Provider>,
+    {
+        let mut sink = Self::empty(dir).await?;
+        for provider in providers {
+            for bucket in provider.buckets() {
+                sink.bucket_file(provider.name(), bucket).await?;
+            }
+            sink.detail_file(
+                provider.name(),
+                provider.balance_columns(),
+                provider.detail_columns(),
+            )
+            .await?;
+        }
+        Ok(sink)
+    }
+
+    /// Like `create`, but for runs mixing `providers`: every report goes to
+    /// one `{name}_detail.csv` with a `provider` column, and a provider's
+    /// bucket files are only created once it has a key.
+    pub async fn create_combined<'p, I>(
+        providers: I,
+        name: &'static str,
+        dir: &Path,
+    ) -> anyhow::Result<Self>
+    where
+        I: IntoIterator<Item = &'p dyn Provider> + Clone,
+    {
+        let mut sink = Self::empty(dir).await?;
+        let (balance_columns, detail_columns) = combined_columns(providers.clone());
+        let (file, tmp, path) = create_temp(dir, &format!("{name}_detail.csv")).await?;
+        sink.pending.push((tmp, path));
+        let detail = CsvSink::new(file, &balance_columns)
+            .with_detail_columns(&detail_columns)
+            .with_provider_column();
+        sink.details.insert(name, detail);
+        sink.combined = Some(name);
+        for provider in providers {
+            sink.deferred.insert(provider.name(), provider.buckets());
+        }
+        Ok(sink)
+    }
+
+    async fn empty(dir: &Path) -> anyhow::Result<Self> {
+        tokio::fs::create_dir_all(dir).await?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            files: HashMap::new(),
+            details: HashMap::new(),
+            pending: Vec::new(),
+            totals: Vec::new(),
+            deferred: HashMap::new(),
+            combined: None,
+        })
+    }
+
+    /// Creates the bucket files of reports that belong to no single provider,
+    /// like those of `auto` runs under the name `auto`, and their
+    /// `*_detail.csv` unless every report shares one.
+    pub async fn add_buckets(
+        &mut self,
+        provider: &'static str,
+        buckets: &[&'static str],
+    ) -> anyhow::Result<()> {
+        for bucket in buckets {
+            self.bucket_file(provider, bucket).await?;
+        }
+        if self.combined.is_none() {
+            self.detail_file(provider, &[], &[]).await?;
+        }
+        Ok(())
+    }
+
+    async fn bucket_file(
+        &mut self,
+        provider: &'static str,
+        bucket: &'static str,
+    ) -> anyhow::Result<&mut File> {
+        match self.files.entry((provider, bucket)) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => {
+                let name = format!("{provider}_{bucket}");
+                let (file, tmp, path) = create_temp(&self.dir, &name).await?;
+                self.pending.push((tmp, path));
+                Ok(entry.insert(file))
+            }
+        }
+    }
+
+    async fn detail_file(
+        &mut self,
+        provider: &'static str,
+        balance_columns: &[&'static str],
+        detail_columns: &[&'static str],
+    ) -> anyhow::Result<&mut CsvSink<File>> {
+        match self.details.entry(provider) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => {
+                let name = format!("{provider}_detail.csv");
+                let (file, tmp, path) = create_temp(&self.dir, &name).await?;
+                self.pending.push((tmp, path));
+                let sink = CsvSink::new(file, balance_columns).with_detail_columns(detail_columns);
+                Ok(entry.insert(sink))
+            }
+        }
+    }
+
+    async fn write_report(&mut self, report: &KeyReport) -> anyhow::Result<()> {
+        if let Some(buckets) = self.deferred.remove(report.provider) {
+            for bucket in buckets {
+                self.bucket_file(report.provider, bucket).await?;
+            }
+        }
+        for bucket in &report.buckets {
+            let file = self.bucket_file(report.provider, bucket).await?;
+            write_line(file, &report.key).await?;
+        }
+        let detail = self.combined.unwrap_or(report.provider);
+        let detail = self.detail_file(detail, &[], &[]).await?;
+        detail.write_report(report).await?;
+
+        if report.status == KeyStatus::Valid {
+            for balance in &report.balances {
+                match self.totals.iter_mut().find(|(provider, name, _)| {
+                    *provider == report.provider && *name == balance.name
+                }) {
+                    Some((_, _, total)) => *total += balance.amount,
+                    None => self
+                        .totals
+                        .push((report.provider, balance.name, balance.amount)),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn commit(&mut self) -> anyhow::Result<()> {
+        for (_, mut file) in self.files.drain() {
+            file.flush().await?;
+        }
+        for (_, mut detail) in self.details.drain() {
+            detail.finish().await?;
+        }
+        while let Some((tmp, path)) = self.pending.last() {
+            tokio::fs::rename(tmp, path).await?;
+            self.pending.pop();
+        }
+        Ok(())
+    }
+}
+
+impl Drop for BucketSink {
+    fn drop(&mut self) {
+        for (tmp, _) in &self.pending {
+            if let Err(err) = std::fs::remove_file(tmp) {
+                tracing::warn!("failed to remove {}: {err}", tmp.display());
+            }
+        }
+    }
+}
+
+impl ReportSink for BucketSink {
+    fn write<'a>(&'a mut self, report: &'a KeyReport) -> BoxFuture<'a, anyhow::Result<()>> {
+        self.write_report(report).boxed()
+    }
+
+    fn finish(&mut self) -> BoxFuture<'_, anyhow::Result<()>> {
+        let mut providers = Vec::new();
+        for (provider, _, _) in &self.totals {
+            if !providers.contains(provider) {
+                providers.push(*provider);
+            }
+        }
+        for provider in providers {
+            let summary = self
+                .totals
+                .iter()
+                .filter(|(p, _, _)| *p == provider)
+                .map(|(_, name, total)| format!("{name}: {total}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            tracing::info!("{provider} {summary}");
+        }
+        self.commit().boxed()
+    }
+}
+
+/// Balance and detail columns of `providers` together, each once and in
+/// order, for output that mixes them.
+pub fn combined_columns<'p, I>(providers: I) -> (Vec<&'static str>, Vec<&'static str>)
+where
+    I: IntoIterator<Item = &'p dyn Provider>,
+{
+    let mut balance_columns = Vec::new();
+    let mut detail_columns = Vec::new();
+    for provider in providers {
+        for column in provider.balance_columns() {
+            if !balance_columns.contains(column) {
+                balance_columns.push(*column);
+            }
+        }
+        for column in provider.detail_columns() {
+            if !detail_columns.contains(column) {
+                detail_columns.push(*column);
+            }
+        }
+    }
+    (balance_columns, detail_columns)
+}
+
+/// Creates a hidden temp file next to `dir/name`, named uniquely so runs
+/// sharing `dir` do not write into each other's.
+async fn create_temp(dir: &Path, name: &str) -> anyhow::Result<(File, PathBuf, PathBuf)> {
+    let suffix = format!("{}-{:08x}", std::process::id(), fastrand::u32(..));
+    let tmp = dir.join(format!(".{name}.{suffix}.tmp"));
+    let file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&tmp)
+        .await
+        .with_context(|| format!("failed to create {}", tmp.display()))?;
+    Ok((file, tmp, dir.join(name)))
+}
+
+/// One JSON object per line.
+pub struct NdjsonSink<W> {
+    writer: W,
+}
+
+impl<W> NdjsonSink<W>
+where
+    W: AsyncWrite + Unpin + Send,
+{
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+}
+
+impl<W> ReportSink for NdjsonSink<W>
+where
+    W: AsyncWrite + Unpin + Send,
+{
+    fn write<'a>(&'a mut self, report: &'a KeyReport) -> BoxFuture<'a, anyhow::Result<()>> {
+        async move {
+            let mut line = serde_json::to_vec(report)?;
+            line.push(b'\n');
+            self.writer.write_all(&line).await?;
+            self.writer.flush().await?;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn finish(&mut self) -> BoxFuture<'_, anyhow::Result<()>> {
+        async move {
+            self.writer.flush().await?;
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+/// RFC 4180 CSV with the columns `key, http_status, classification`, then the
+/// given balance columns, then `currency`, the detail columns if any, and
+/// `checked_at, error`.
+pub struct CsvSink<W> {
+    writer: W,
+    balance_columns: Vec<&'static str>,
+    detail_columns: Vec<&'static str>,
+    provider_column: bool,
+    header_written: bool,
+}
+
+impl<W> CsvSink<W>
+where
+    W: AsyncWrite + Unpin + Send,
+{
+    pub fn new(writer: W, balance_columns: &[&'static str]) -> Self {
+        Self {
+            writer,
+            balance_columns: balance_columns.to_vec(),
+            detail_columns: Vec::new(),
+            provider_column: false,
+            header_written: false,
+        }
+    }
+
+    /// Prepends a `provider` column, for output that mixes providers.
+    pub fn with_provider_column(mut self) -> Self {
+        self.provider_column = true;
+        self
+    }
+
+    /// Adds columns for `KeyReport::details` after the currency.
+    pub fn with_detail_columns(mut self, detail_columns: &[&'static str]) -> Self {
+        self.detail_columns = detail_columns.to_vec();
+        self
+    }
+
+    async fn write_row(&mut self, fields: &[&str]) -> anyhow::Result<()> {
+        let mut row = fields
+            .iter()
+            .map(|f| csv_field(f))
+            .collect::<Vec<_>>()
+            .join(",");
+        row.push_str("\r\n");
+        self.writer.write_all(row.as_bytes()).await?;
+        Ok(())
+    }
+
+    async fn write_header(&mut self) -> anyhow::Result<()> {
+        let mut header = Vec::new();
+        if self.provider_column {
+            header.push("provider");
+        }
+        header.extend(["key", "http_status", "classification"]);
+        header.extend(&self.balance_columns);
+        header.push("currency");
+        header.extend(&self.detail_columns);
+        header.extend(["checked_at", "error"]);
+        self.write_row(&header).await
+    }
+
+    async fn write_report(&mut self, report: &KeyReport) -> anyhow::Result<()> {
+        if !self.header_written {
+            self.write_header().await?;
+            self.header_written = true;
+        }
+        let http_status = report.http_status.map(|s| s.to_string());
+        let balances = self
+            .balance_columns
+            .iter()
+            .map(|name| report.balance(name).map(|b| b.to_string()))
+            .collect::<Vec<_>>();
+        let checked_at = format_rfc3339(report.checked_at);
+        let mut row = Vec::new();
+        if self.provider_column {
+            row.push(report.provider);
+        }
+        row.extend([
+            report.key.as_str(),
+            http_status.as_deref().unwrap_or_default(),
+            report.status.as_str(),
+        ]);
+        row.extend(balances.iter().map(|b| b.as_deref().unwrap_or_default()));
+        row.push(report.currency().unwrap_or_default());
+        row.extend(
+            self.detail_columns
+                .iter()
+                .map(|name| report.detail(name).unwrap_or_default()),
+        );
+        row.extend([&checked_at, report.error.as_deref().unwrap_or_default()]);
+        self.write_row(&row).await?;
+        self.writer.flush().await?;
+        Ok(())
+    }
+}
+
+impl<W> ReportSink for CsvSink<W>
+where
+    W: AsyncWrite + Unpin + Send,
+{
+    fn write<'a>(&'a mut self, report: &'a KeyReport) -> BoxFuture<'a, anyhow::Result<()>> {
+        self.write_report(report).boxed()
+    }
+
+    fn finish(&mut self) -> BoxFuture<'_, anyhow::Result<()>> {
+        async move {
+            if !self.header_written {
+                self.write_header().await?;
+                self.header_written = true;
+            }
+            self.writer.flush().await?;
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+/// Human-readable, column-aligned listing on stdout.
+pub struct TableSink {
+    stdout: tokio::io::Stdout,
+    header_written: bool,
+    counts: Vec<(KeyStatus, usize)>,
+}
+
+impl TableSink {
+    pub fn new() -> Self {
+        Self {
+            stdout: tokio::io::stdout(),
+            header_written: false,
+            counts: Vec::new(),
+        }
+    }
+}
+
+impl Default for TableSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReportSink for TableSink {
+    fn write<'a>(&'a mut self, report: &'a KeyReport) -> BoxFuture<'a, anyhow::Result<()>> {
+        async move {
+            let mut out = String::new();
+            if !self.header_written {
+                out.push_str(&format!(
+                    "{:<12} {:<18} {:>4}  {:<56} BALANCE\n",
+                    "PROVIDER", "STATUS", "HTTP", "KEY"
+                ));
+                self.header_written = true;
+            }
+            let http_status = report
+                .http_status
+                .map(|s| s.to_string())
+                .unwrap_or_else(|| "-".to_string());
+            let balances = report
+                .balances
+                .iter()
+                .map(|b| format!("{}={}", b.name, b.amount))
+                .collect::<Vec<_>>()
+                .join(" ");
+            out.push_str(&format!(
+                "{:<12} {:<18} {:>4}  {:<56} {}\n",
+                report.provider,
+                report.status.as_str(),
+                http_status,
+                report.key,
+                balances
+            ));
+            self.stdout.write_all(out.as_bytes()).await?;
+            self.stdout.flush().await?;
+            match self.counts.iter_mut().find(|(s, _)| *s == report.status) {
+                Some((_, count)) => *count += 1,
+                None => self.counts.push((report.status, 1)),
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn finish(&mut self) -> BoxFuture<'_, anyhow::Result<()>> {
+        async move {
+            let summary = self
+                .counts
+                .iter()
+                .map(|(status, count)| format!("{}: {count}", status.as_str()))
+                .collect::<Vec<_>>()
+                .join(", ");
+            self.stdout
+                .write_all(format!("\n{summary}\n").as_bytes())
+                .await?;
+            self.stdout.flush().await?;
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+async fn write_line(file: &mut File, line: &str) -> anyhow::Result<()> {
+    file.write_all(format!("{line}\n").as_bytes()).await?;
+    file.flush().await?;
+    Ok(())
+}
+
+/// Replaces every key with its `RedactedKey` form before passing the report on.
+pub struct RedactingSink<S> {
+    inner: S,
+}
+
+impl<S> RedactingSink<S>
+where
+    S: ReportSink,
+{
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+}
+
+impl<S> ReportSink for RedactingSink<S>
+where
+    S: ReportSink,
+{
+    fn write<'a>(&'a mut self, report: &'a KeyReport) -> BoxFuture<'a, anyhow::Result<()>> {
+        async move {
+            let redacted = KeyReport {
+                key: RedactedKey::new(&report.key).to_string(),
+                ..report.clone()
+            };
+            self.inner.write(&redacted).await
+        }
+        .boxed()
+    }
+
+    fn finish(&mut self) -> BoxFuture<'_, anyhow::Result<()>> {
+        self.inner.finish()
+    }
+}
+
+/// Name of a per-run output folder, e.g. `20261018T052403Z`.
+pub fn run_folder_name(time: SystemTime) -> String {
+    format_rfc3339(time).replace(['-', ':'], "")
+}
+
+/// Creates a new, empty run folder inside `dir` named after `time`. A folder
+/// of the same name is never reused: runs started in the same second get
+/// `-2`, `-3`, ... appended.
+pub async fn create_run_folder(dir: &Path, time: SystemTime) -> anyhow::Result<PathBuf> {
+    tokio::fs::create_dir_all(dir).await?;
+    let name = run_folder_name(time);
+    for n in 1.. {
+        let path = match n {
+            1 => dir.join(&name),
+            _ => dir.join(format!("{name}-{n}")),
+        };
+        match tokio::fs::create_dir(&path).await {
+            Ok(()) => return Ok(path),
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err.into()),
+        }
+    }
+    unreachable!("ran out of run folder names")
+}
+
+/// Quotes a field per RFC 4180 when it contains a separator, quote or newline.
+pub fn csv_field(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\r', '\n']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+pub async fn save_reports(
+    provider: &dyn Provider,
+    reports: &[KeyReport],
+    dir: &Path,
+) -> anyhow::Result<()> {
+    let mut sink = BucketSink::create([provider], dir).await?;
+    for report in reports {
+        sink.write(report).await?;
+    }
+    sink.finish().await
+}
@@ -0,0 +1,261 @@
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use reqwest::{header::HeaderMap, Client};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    client::ClientOptions,
+    error::ProbeError,
+    limiter::Limits,
+    report::{Balance, Detail, KeyStatus},
+    rules::ProviderRules,
+    validate::{self, Malformed},
+};
+
+/// Buckets of `ProbeError::bucket` and malformed keys, which every balance
+/// provider writes after its own.
+pub const ERROR_BUCKETS: [&str; 8] = [
+    "invalid_keys",
+    "forbidden_keys",
+    "429_keys",
+    "server_err_keys",
+    "http_err_keys",
+    "decode_err_keys",
+    "failed_keys",
+    "malformed_keys",
+];
+
+/// `own` followed by `ERROR_BUCKETS`, for `Provider::buckets`. `M` must be
+/// `N + ERROR_BUCKETS.len()`.
+pub const fn with_error_buckets<const N: usize, const M: usize>(
+    own: [&'static str; N],
+) -> [&'static str; M] {
+    assert!(N + ERROR_BUCKETS.len() == M);
+    let mut buckets = [""; M];
+    let mut i = 0;
+    while i < M {
+        buckets[i] = match i < N {
+            true => own[i],
+            false => ERROR_BUCKETS[i - N],
+        };
+        i += 1;
+    }
+    buckets
+}
+
+pub struct Response {
+    pub status: u16,
+    pub headers: HeaderMap,
+    pub text: String,
+}
+
+impl Response {
+    pub async fn read(resp: reqwest::Response) -> reqwest::Result<Self> {
+        let status = resp.status().as_u16();
+        let headers = resp.headers().clone();
+        let text = resp.text().await?;
+        Ok(Self {
+            status,
+            headers,
+            text,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Classification {
+    pub status: KeyStatus,
+    pub buckets: Vec<&'static str>,
+    pub balances: Vec<Balance>,
+    pub details: Vec<Detail>,
+    pub error: Option<String>,
+}
+
+impl Classification {
+    pub fn new(status: KeyStatus, bucket: &'static str) -> Self {
+        Self {
+            status,
+            buckets: vec![bucket],
+            balances: Vec::new(),
+            details: Vec::new(),
+            error: None,
+        }
+    }
+
+    pub fn with_balance(mut self, name: &'static str, amount: f64, currency: Option<&str>) -> Self {
+        self.balances.push(Balance {
+            name,
+            amount,
+            currency: currency.map(String::from),
+        });
+        self
+    }
+
+    pub fn with_detail(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.details.push(Detail {
+            name,
+            value: value.into(),
+        });
+        self
+    }
+
+    pub fn failed(err: ProbeError) -> Self {
+        Self::new(err.key_status(), err.bucket()).with_error(err.to_string())
+    }
+
+    pub fn with_error(mut self, error: String) -> Self {
+        self.error = Some(error);
+        self
+    }
+}
+
+/// The part of a provider the command line and config can change.
+#[derive(Debug, Clone)]
+pub struct Endpoint {
+    /// Root of the provider's API, e.g. `https://api.deepseek.com`, without a
+    /// trailing slash.
+    pub base_url: String,
+    /// Consulted by `Provider::classify` before its own logic.
+    pub rules: ProviderRules,
+}
+
+impl Endpoint {
+    /// `base_url` with the built-in rules of `provider`.
+    pub fn new(provider: &str, base_url: &str) -> Self {
+        Self {
+            base_url: base_url.to_string(),
+            rules: ProviderRules::builtin(provider),
+        }
+    }
+
+    /// `path`, like `/models`, under `base_url`.
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{path}", self.base_url)
+    }
+
+    /// The classification of the first rule matching `resp`.
+    pub fn classify(
+        &self,
+        resp: &Response,
+        buckets: &'static [&'static str],
+    ) -> Option<Classification> {
+        self.rules.classify(resp.status, &resp.text, buckets)
+    }
+}
+
+pub trait Provider: Send + Sync {
+    /// Prefix of every output file, e.g. `deepseek_key`.
+    fn name(&self) -> &'static str;
+
+    fn endpoint_mut(&mut self) -> &mut Endpoint;
+
+    fn set_base_url(&mut self, base_url: String) {
+        self.endpoint_mut().base_url = base_url;
+    }
+
+    /// Replaces the built-in `ProviderRules` consulted by `classify`.
+    fn set_rules(&mut self, rules: ProviderRules) {
+        self.endpoint_mut().rules = rules;
+    }
+
+    /// Output buckets in the order they are written.
+    fn buckets(&self) -> &'static [&'static str];
+
+    /// Bucket for keys whose request could not be sent at all.
+    fn error_bucket(&self) -> &'static str {
+        "failed_keys"
+    }
+
+    /// Balance columns of the `*_detail.csv` file, matching `Balance::name`.
+    fn balance_columns(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Detail columns of the `*_detail.csv` file, matching `Detail::name`.
+    fn detail_columns(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    fn default_limits(&self) -> Limits {
+        Limits {
+            rps: 3.0,
+            concurrency: 8,
+            burst: 3,
+        }
+    }
+
+    /// Balance endpoints answer quickly; a slow one is better given up on.
+    fn default_client_options(&self) -> ClientOptions {
+        ClientOptions {
+            timeout: Some(Duration::from_secs(15)),
+            connect_timeout: Some(Duration::from_secs(5)),
+            ..ClientOptions::default()
+        }
+    }
+
+    /// Checks the key's shape before any request is sent.
+    fn validate(&self, key: &str) -> Result<(), Malformed> {
+        validate::check_common(key)
+    }
+
+    fn probe<'a>(
+        &'a self,
+        key: &'a str,
+        client: &'a Client,
+    ) -> BoxFuture<'a, Result<Response, ProbeError>>;
+
+    fn classify(&self, resp: &Response) -> Classification;
+}
+
+pub(crate) async fn get_with_bearer(
+    url: String,
+    key: &str,
+    client: &Client,
+) -> Result<Response, ProbeError> {
+    let resp = client
+        .get(url)
+        .header("Authorization", format!("Bearer {key}"))
+        .send()
+        .await?;
+    Ok(Response::read(resp).await?)
+}
+
+/// Classifies a non-200 answer from a balance endpoint.
+pub(crate) fn classify_failure(resp: &Response) -> Classification {
+    Classification::failed(ProbeError::from_response(resp))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ModelList {
+    data: Vec<Model>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Model {
+    id: String,
+}
+
+/// Classifies the answer of an OpenAI-style `GET /models`: by the rules first,
+/// then any other non-200 answer as a failure. A model list means the key
+/// works, and the ids in it are recorded space-separated as the `models`
+/// detail.
+pub(crate) fn classify_model_list(
+    endpoint: &Endpoint,
+    resp: &Response,
+    buckets: &'static [&'static str],
+) -> Classification {
+    if let Some(classification) = endpoint.classify(resp, buckets) {
+        return classification;
+    }
+    if resp.status != 200 {
+        return classify_failure(resp);
+    }
+    match serde_json::from_str::<ModelList>(&resp.text) {
+        Ok(models) => {
+            let ids = models.data.into_iter().map(|m| m.id).collect::<Vec<_>>();
+            Classification::new(KeyStatus::Valid, "key").with_detail("models", ids.join(" "))
+        }
+        Err(err) => Classification::failed(ProbeError::decode(resp.status, err)),
+    }
+}
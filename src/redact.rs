@@ -0,0 +1,46 @@
+use std::fmt;
+
+/// Displays an API key as its first and last few characters plus a short hash,
+/// e.g. `sk-a…9f3c#1b2e4d`, so log lines stay correlatable without leaking it.
+#[derive(Clone, Copy)]
+pub struct RedactedKey<'a>(&'a str);
+
+impl<'a> RedactedKey<'a> {
+    const VISIBLE: usize = 4;
+
+    pub fn new(key: &'a str) -> Self {
+        Self(key)
+    }
+
+    /// Stable across runs and builds (FNV-1a), unlike `DefaultHasher`.
+    fn short_hash(&self) -> u32 {
+        let hash = self.0.bytes().fold(0xcbf29ce484222325_u64, |hash, b| {
+            (hash ^ u64::from(b)).wrapping_mul(0x100000001b3)
+        });
+        (hash >> 40) as u32
+    }
+}
+
+impl fmt::Display for RedactedKey<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let chars = self.0.chars().count();
+        if chars > Self::VISIBLE * 3 {
+            let prefix = self.0.chars().take(Self::VISIBLE).collect::<String>();
+            let suffix = self
+                .0
+                .chars()
+                .skip(chars - Self::VISIBLE)
+                .collect::<String>();
+            write!(f, "{prefix}…{suffix}")?;
+        } else {
+            f.write_str("…")?;
+        }
+        write!(f, "#{:06x}", self.short_hash())
+    }
+}
+
+impl fmt::Debug for RedactedKey<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
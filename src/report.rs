@@ -0,0 +1,108 @@
+use std::{fmt, time::SystemTime};
+
+use serde::{Deserialize, Serialize, Serializer};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyStatus {
+    Valid,
+    NoBalance,
+    NegativeBalance,
+    Disabled,
+    Invalid,
+    RateLimited,
+    RegionBlocked,
+    Unknown,
+    Malformed,
+    /// Listed again in the input after its first occurrence, which is checked.
+    Duplicate,
+}
+
+impl KeyStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            KeyStatus::Valid => "valid",
+            KeyStatus::NoBalance => "no_balance",
+            KeyStatus::NegativeBalance => "negative_balance",
+            KeyStatus::Disabled => "disabled",
+            KeyStatus::Invalid => "invalid",
+            KeyStatus::RateLimited => "rate_limited",
+            KeyStatus::RegionBlocked => "region_blocked",
+            KeyStatus::Unknown => "unknown",
+            KeyStatus::Malformed => "malformed",
+            KeyStatus::Duplicate => "duplicate",
+        }
+    }
+}
+
+impl fmt::Display for KeyStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Balance {
+    pub name: &'static str,
+    pub amount: f64,
+    pub currency: Option<String>,
+}
+
+/// Something a provider tells about a key besides its balance, like the
+/// organization it belongs to.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Detail {
+    pub name: &'static str,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyReport {
+    pub key: String,
+    pub provider: &'static str,
+    /// Named like the CSV column, next to `http_status`.
+    #[serde(rename = "classification")]
+    pub status: KeyStatus,
+    pub balances: Vec<Balance>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub details: Vec<Detail>,
+    pub http_status: Option<u16>,
+    pub body: Option<String>,
+    pub error: Option<String>,
+    #[serde(serialize_with = "serialize_rfc3339")]
+    pub checked_at: SystemTime,
+    /// Output buckets the key is filed under, relative to the provider prefix.
+    #[serde(skip)]
+    pub buckets: Vec<&'static str>,
+}
+
+impl KeyReport {
+    pub fn balance(&self, name: &str) -> Option<f64> {
+        self.balances
+            .iter()
+            .find(|b| b.name == name)
+            .map(|b| b.amount)
+    }
+
+    pub fn detail(&self, name: &str) -> Option<&str> {
+        self.details
+            .iter()
+            .find(|d| d.name == name)
+            .map(|d| d.value.as_str())
+    }
+
+    pub fn currency(&self) -> Option<&str> {
+        self.balances.iter().find_map(|b| b.currency.as_deref())
+    }
+}
+
+pub fn format_rfc3339(time: SystemTime) -> String {
+    humantime::format_rfc3339_seconds(time).to_string()
+}
+
+fn serialize_rfc3339<S>(time: &SystemTime, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(&format_rfc3339(*time))
+}
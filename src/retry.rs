@@ -0,0 +1,67 @@
+use std::time::{Duration, SystemTime};
+
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+
+use crate::{error::ProbeError, provider::Response};
+
+/// When to send a request again.
+///
+/// Transport errors, 5xx and 429 responses are retried with jittered
+/// exponential backoff, or after the server's `Retry-After` when it sends one.
+/// Anything else, 401/403 in particular, is final on the first answer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// Total tries per key, including the first one.
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    /// Longest wait between tries. A `Retry-After` beyond it ends retrying.
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// How long to wait before another try after `attempt` tries (1-based),
+    /// or `None` when `resp` is final.
+    pub fn delay(&self, attempt: u32, resp: &Result<Response, ProbeError>) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+        match resp {
+            Err(_) => Some(self.backoff(attempt)),
+            Ok(resp) if resp.status == 429 || resp.status >= 500 => {
+                match retry_after(&resp.headers) {
+                    Some(wait) if wait > self.max_delay => None,
+                    Some(wait) => Some(wait),
+                    None => Some(self.backoff(attempt)),
+                }
+            }
+            Ok(_) => None,
+        }
+    }
+
+    /// "Full jitter": uniform in `[0, min(max_delay, base_delay * 2^(attempt - 1))]`.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exp = 2_u32.saturating_pow(attempt.saturating_sub(1));
+        let ceiling = self.base_delay.saturating_mul(exp).min(self.max_delay);
+        ceiling.mul_f64(fastrand::f64())
+    }
+}
+
+/// Parses `Retry-After` as either delay-seconds or an HTTP date.
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+}
@@ -0,0 +1,216 @@
+use std::{cell::OnceCell, collections::HashMap, path::Path, sync::OnceLock};
+
+use anyhow::Context;
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+
+use crate::{
+    provider::{Classification, Provider},
+    report::KeyStatus,
+};
+
+/// Classification rules per provider, keyed by `Provider::name`. See
+/// `rules.toml` for the format.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct Rules(HashMap<String, ProviderRules>);
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProviderRules {
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+    /// Lowest balance still counted as valid, for providers that use one.
+    pub valid_balance: Option<f64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    #[serde(default)]
+    pub status: Vec<u16>,
+    pub contains: Option<String>,
+    pub not_contains: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_regex")]
+    pub regex: Option<Regex>,
+    pub pointer: Option<String>,
+    pub equals: Option<String>,
+    pub classification: KeyStatus,
+    pub bucket: String,
+}
+
+impl Rules {
+    /// The rules shipped in `rules.toml`.
+    pub fn builtin() -> &'static Rules {
+        static BUILTIN: OnceLock<Rules> = OnceLock::new();
+        BUILTIN.get_or_init(|| {
+            toml::from_str(include_str!("rules.toml")).expect("built-in rules.toml is valid")
+        })
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        toml::from_str(&contents).with_context(|| format!("failed to parse {}", path.display()))
+    }
+
+    /// Rejects rules for providers not among `providers`, and balance
+    /// thresholds of providers that report no balance.
+    pub fn check(&self, providers: &[Box<dyn Provider>]) -> anyhow::Result<()> {
+        let mut names = self.0.keys().collect::<Vec<_>>();
+        names.sort();
+        for name in names {
+            let Some(provider) = providers.iter().find(|p| p.name() == name) else {
+                let names = providers.iter().map(|p| p.name()).collect::<Vec<_>>();
+                anyhow::bail!(
+                    "unknown provider `{name}` in the rules, expected one of {}",
+                    names.join(", ")
+                );
+            };
+            anyhow::ensure!(
+                self.0[name].valid_balance.is_none() || !provider.balance_columns().is_empty(),
+                "{name}: valid_balance is not supported, {name} reports no balance"
+            );
+        }
+        Ok(())
+    }
+
+    /// Sets `provider`'s `valid_balance` unless these rules already do.
+    pub fn or_valid_balance(mut self, provider: &str, valid_balance: f64) -> Self {
+        let rules = self.0.entry(provider.to_string()).or_default();
+        rules.valid_balance.get_or_insert(valid_balance);
+        self
+    }
+
+    /// `provider`'s rules from this file followed by the built-in ones. Its
+    /// settings replace the built-in values.
+    pub fn for_provider(&self, provider: &str) -> ProviderRules {
+        let builtin = ProviderRules::builtin(provider);
+        let Some(custom) = self.0.get(provider) else {
+            return builtin;
+        };
+        ProviderRules {
+            rules: custom.rules.iter().chain(&builtin.rules).cloned().collect(),
+            valid_balance: custom.valid_balance.or(builtin.valid_balance),
+        }
+    }
+}
+
+impl ProviderRules {
+    pub fn builtin(provider: &str) -> Self {
+        Rules::builtin()
+            .0
+            .get(provider)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Whether `balance` makes a key valid: above zero and at least
+    /// `valid_balance`, if set.
+    pub fn is_valid_balance(&self, balance: f64) -> bool {
+        balance > 0.0 && self.valid_balance.is_none_or(|v| balance >= v)
+    }
+
+    /// Rejects rules that could never apply as written.
+    pub fn check(&self, buckets: &[&str]) -> anyhow::Result<()> {
+        for rule in &self.rules {
+            anyhow::ensure!(
+                buckets.contains(&rule.bucket.as_str()),
+                "unknown bucket `{}`, expected one of {}",
+                rule.bucket,
+                buckets.join(", ")
+            );
+            anyhow::ensure!(
+                rule.equals.is_none() || rule.pointer.is_some(),
+                "`equals = {:?}` needs a `pointer`",
+                rule.equals.as_deref().unwrap_or_default()
+            );
+        }
+        Ok(())
+    }
+
+    /// Classifies an answer by the first rule it matches, if any. Only
+    /// buckets listed in `buckets` are used; `check` reports the others.
+    pub fn classify(
+        &self,
+        status: u16,
+        body: &str,
+        buckets: &'static [&'static str],
+    ) -> Option<Classification> {
+        let json = OnceCell::new();
+        let (rule, bucket) = self
+            .rules
+            .iter()
+            .filter(|rule| rule.matches(status, body, &json))
+            .find_map(|rule| {
+                let bucket = buckets.iter().find(|b| **b == rule.bucket)?;
+                Some((rule, *bucket))
+            })?;
+        let classification = Classification::new(rule.classification, bucket);
+        Some(match status {
+            200 => classification,
+            _ => classification.with_error(body.to_string()),
+        })
+    }
+}
+
+impl Rule {
+    fn matches(&self, status: u16, body: &str, json: &OnceCell<Option<Value>>) -> bool {
+        if !self.status.is_empty() && !self.status.contains(&status) {
+            return false;
+        }
+        if !self
+            .contains
+            .as_ref()
+            .is_none_or(|s| body.contains(s.as_str()))
+        {
+            return false;
+        }
+        if self
+            .not_contains
+            .as_ref()
+            .is_some_and(|s| body.contains(s.as_str()))
+        {
+            return false;
+        }
+        if !self.regex.as_ref().is_none_or(|re| re.is_match(body)) {
+            return false;
+        }
+        let Some(pointer) = &self.pointer else {
+            return true;
+        };
+        let json = json.get_or_init(|| serde_json::from_str(body).ok());
+        let Some(value) = json.as_ref().and_then(|json| json.pointer(pointer)) else {
+            return false;
+        };
+        match (&self.equals, value) {
+            (None, _) => true,
+            (Some(expected), Value::String(value)) => value == expected,
+            (Some(expected), value) => {
+                serde_json::from_str::<Value>(expected).is_ok_and(|expected| expected == *value)
+            }
+        }
+    }
+}
+
+fn deserialize_regex<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Regex>, D::Error> {
+    let Some(pattern) = Option::<String>::deserialize(d)? else {
+        return Ok(None);
+    };
+    Regex::new(&pattern)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+/// Gives each provider its rules from `rules` merged with the built-in ones.
+pub fn apply_rules(providers: &mut [Box<dyn Provider>], rules: &Rules) -> anyhow::Result<()> {
+    for provider in providers {
+        let rules = rules.for_provider(provider.name());
+        rules
+            .check(provider.buckets())
+            .with_context(|| format!("{} rules", provider.name()))?;
+        provider.set_rules(rules);
+    }
+    Ok(())
+}
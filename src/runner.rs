@@ -0,0 +1,411 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use futures::{future::BoxFuture, stream::FuturesUnordered, Future, FutureExt, Stream, StreamExt};
+use reqwest::Client;
+use tokio::sync::Semaphore;
+use tracing::{instrument, Instrument};
+
+use crate::{
+    detect,
+    error::ProbeError,
+    limiter::{LimitOverrides, Limits, RateChange, RateLimiter},
+    provider::{Provider, Response},
+    redact::RedactedKey,
+    report::{KeyReport, KeyStatus},
+    retry::RetryPolicy,
+    validate::{self, Malformed},
+};
+
+/// Buckets of `auto` runs for keys no provider could take, written under the
+/// name `auto`.
+pub const AUTO_BUCKETS: &[&str] = &["invalid_keys", "unrecognized_keys", "malformed_keys"];
+
+/// Buckets for keys the input file itself gets wrong, written under the name
+/// `input`.
+pub const INPUT_BUCKETS: &[&str] = &["duplicate_keys"];
+
+/// Settings shared by every key of a run.
+#[derive(Debug, Clone)]
+pub struct RunOptions {
+    /// Applies to every provider, over `provider_limits`.
+    pub limits: LimitOverrides,
+    /// Keyed by `Provider::name`.
+    pub provider_limits: HashMap<String, LimitOverrides>,
+    pub retry: RetryPolicy,
+    /// Times a rate-limited key goes back to the end of the queue before its
+    /// report is final.
+    pub requeues: u32,
+}
+
+impl RunOptions {
+    pub fn limits_for<P: Provider + ?Sized>(&self, provider: &P) -> Limits {
+        let mut limits = provider.default_limits();
+        if let Some(overrides) = self.provider_limits.get(provider.name()) {
+            limits = limits.with_overrides(overrides);
+        }
+        limits.with_overrides(&self.limits)
+    }
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        Self {
+            limits: LimitOverrides::default(),
+            provider_limits: HashMap::new(),
+            retry: RetryPolicy::default(),
+            requeues: 2,
+        }
+    }
+}
+
+/// Runs `check` on up to `concurrency` items at once. Items reported as rate
+/// limited are queued again behind the rest, up to `requeues` times each.
+fn run_concurrently<'a, T, F, Fut>(
+    items: Vec<T>,
+    concurrency: usize,
+    requeues: u32,
+    check: F,
+) -> impl Stream<Item = KeyReport> + Send + 'a
+where
+    T: Clone + Send + 'a,
+    F: FnMut(T) -> Fut + Send + 'a,
+    Fut: Future<Output = KeyReport> + Send + 'a,
+{
+    let queue = items
+        .into_iter()
+        .map(|item| (item, 0))
+        .collect::<VecDeque<_>>();
+    let in_flight = FuturesUnordered::<BoxFuture<'a, (T, u32, KeyReport)>>::new();
+    futures::stream::unfold(
+        (queue, in_flight, check),
+        move |(mut queue, mut in_flight, mut check)| async move {
+            loop {
+                while in_flight.len() < concurrency.max(1) {
+                    let Some((item, requeued)) = queue.pop_front() else {
+                        break;
+                    };
+                    let report = check(item.clone());
+                    in_flight.push(async move { (item, requeued, report.await) }.boxed());
+                }
+                let (item, requeued, report) = in_flight.next().await?;
+                if report.status == KeyStatus::RateLimited && requeued < requeues {
+                    tracing::debug!(
+                        "Requeueing rate limited key: {}",
+                        RedactedKey::new(&report.key)
+                    );
+                    queue.push_back((item, requeued + 1));
+                    continue;
+                }
+                return Some((report, (queue, in_flight, check)));
+            }
+        },
+    )
+}
+
+/// Checks every key and yields each report as soon as its request finishes.
+/// Keys rejected by `Provider::validate` are reported first, without a request.
+pub fn check_stream<'a, P>(
+    provider: &'a P,
+    keys: Vec<String>,
+    options: &RunOptions,
+    client: Client,
+) -> impl Stream<Item = KeyReport> + Send + 'a
+where
+    P: Provider + ?Sized,
+{
+    let limits = options.limits_for(provider);
+    let limiter = Arc::new(RateLimiter::new(&limits));
+    let retry = options.retry;
+    let mut malformed = Vec::new();
+    let mut valid = Vec::new();
+    for key in keys {
+        match provider.validate(&key) {
+            Ok(()) => valid.push(key),
+            Err(reason) => malformed.push(malformed_report(provider.name(), key, reason)),
+        }
+    }
+    let checked = run_concurrently(valid, limits.concurrency, options.requeues, move |key| {
+        let client = client.clone();
+        let limiter = limiter.clone();
+        let span = tracing::debug_span!("probe", key = %RedactedKey::new(&key));
+        async move { probe_key(provider, &limiter, &retry, key, &client).await }.instrument(span)
+    });
+    futures::stream::iter(malformed).chain(checked)
+}
+
+/// Like `check_stream`, but routes every key to the provider its format points
+/// at. Keys matching several providers are tried in `detect::candidates`
+/// order until one does not reject them as invalid; keys all of them reject
+/// are filed under `auto_invalid_keys`. Each provider uses its client from
+/// `clients`, or a default one.
+pub fn check_auto_stream<'a>(
+    providers: &'a [Box<dyn Provider>],
+    keys: Vec<String>,
+    options: &RunOptions,
+    mut clients: HashMap<&'static str, Client>,
+) -> impl Stream<Item = KeyReport> + Send + 'a {
+    let mut lanes = HashMap::new();
+    let mut concurrency = 0;
+    for provider in providers {
+        let limits = options.limits_for(provider.as_ref());
+        concurrency += limits.concurrency.max(1);
+        let lane = Lane {
+            limiter: RateLimiter::new(&limits),
+            slots: Semaphore::new(limits.concurrency.max(1)),
+            client: clients.remove(provider.name()).unwrap_or_default(),
+        };
+        lanes.insert(provider.name(), lane);
+    }
+    let lanes = Arc::new(lanes);
+    let retry = options.retry;
+    let mut unsent = Vec::new();
+    let mut routed = Vec::new();
+    for key in keys {
+        if let Err(reason) = validate::check_common(&key) {
+            unsent.push(malformed_report("auto", key, reason));
+            continue;
+        }
+        let candidates = detect::candidates(&key)
+            .into_iter()
+            .filter_map(|name| providers.iter().find(|p| p.name() == name))
+            .map(|p| p.as_ref())
+            .collect::<Vec<_>>();
+        if candidates.is_empty() {
+            tracing::warn!("Unrecognized key format: {}", RedactedKey::new(&key));
+            unsent.push(unsent_report(
+                "auto",
+                key,
+                KeyStatus::Unknown,
+                "unrecognized_keys",
+                "unrecognized key format".to_string(),
+            ));
+            continue;
+        }
+        let mut valid = Vec::new();
+        let mut reasons = Vec::new();
+        for provider in candidates {
+            match provider.validate(&key) {
+                Ok(()) => valid.push(provider),
+                Err(reason) => reasons.push(format!("{}: {reason}", provider.name())),
+            }
+        }
+        if valid.is_empty() {
+            // The key only looks like theirs; none of the providers would take it.
+            let reasons = reasons.join("; ");
+            tracing::warn!("Malformed key: {}, {reasons}", RedactedKey::new(&key));
+            unsent.push(unsent_report(
+                "auto",
+                key,
+                KeyStatus::Malformed,
+                "malformed_keys",
+                reasons,
+            ));
+            continue;
+        }
+        routed.push((key, valid));
+    }
+    // Enough keys in flight to fill every lane; each lane then limits its
+    // own requests.
+    let checked = run_concurrently(
+        routed,
+        concurrency,
+        options.requeues,
+        move |(key, candidates)| {
+            let lanes = lanes.clone();
+            let span = tracing::debug_span!("probe", key = %RedactedKey::new(&key));
+            async move { route_key(&candidates, &lanes, &retry, key).await }.instrument(span)
+        },
+    );
+    futures::stream::iter(unsent).chain(checked)
+}
+
+/// The rate limiter, request slots and HTTP client of one provider in an
+/// auto run.
+struct Lane {
+    limiter: RateLimiter,
+    /// One permit per request in flight, `Limits::concurrency` in total.
+    slots: Semaphore,
+    client: Client,
+}
+
+async fn route_key(
+    candidates: &[&dyn Provider],
+    lanes: &HashMap<&'static str, Lane>,
+    retry: &RetryPolicy,
+    key: String,
+) -> KeyReport {
+    let mut rejected = Vec::new();
+    let mut last = None;
+    for provider in candidates {
+        let lane = &lanes[provider.name()];
+        let report = {
+            let _slot = lane
+                .slots
+                .acquire()
+                .await
+                .expect("lane semaphores are never closed");
+            probe_key(*provider, &lane.limiter, retry, key.clone(), &lane.client).await
+        };
+        if report.status != KeyStatus::Invalid || candidates.len() == 1 {
+            return report;
+        }
+        rejected.push(provider.name());
+        last = Some(report);
+    }
+    // Every provider the format fits turned the key down, so none of them is
+    // known to be its own.
+    let report = last.expect("routed keys have at least one candidate");
+    KeyReport {
+        provider: "auto",
+        error: Some(format!("rejected by {}", rejected.join(", "))),
+        buckets: vec!["invalid_keys"],
+        ..report
+    }
+}
+
+/// Report for a repeated occurrence of `key`, which is only checked once.
+pub fn duplicate_report(key: String) -> KeyReport {
+    tracing::warn!("Duplicate key: {}", RedactedKey::new(&key));
+    unsent_report(
+        "input",
+        key,
+        KeyStatus::Duplicate,
+        "duplicate_keys",
+        "appears more than once in the input".to_string(),
+    )
+}
+
+fn malformed_report(provider: &'static str, key: String, reason: Malformed) -> KeyReport {
+    tracing::warn!("Malformed key: {}, {reason}", RedactedKey::new(&key));
+    unsent_report(
+        provider,
+        key,
+        KeyStatus::Malformed,
+        "malformed_keys",
+        reason.to_string(),
+    )
+}
+
+fn unsent_report(
+    provider: &'static str,
+    key: String,
+    status: KeyStatus,
+    bucket: &'static str,
+    error: String,
+) -> KeyReport {
+    KeyReport {
+        key,
+        provider,
+        status,
+        balances: Vec::new(),
+        details: Vec::new(),
+        http_status: None,
+        body: None,
+        error: Some(error),
+        checked_at: SystemTime::now(),
+        buckets: vec![bucket],
+    }
+}
+
+async fn probe_key<P>(
+    provider: &P,
+    limiter: &RateLimiter,
+    retry: &RetryPolicy,
+    key: String,
+    client: &Client,
+) -> KeyReport
+where
+    P: Provider + ?Sized,
+{
+    let mut attempt = 1;
+    loop {
+        limiter.acquire().await;
+        let resp = provider.probe(&key, client).await;
+        // A 429 the provider reads as something else, like an exhausted
+        // quota, says nothing about the request rate and waiting won't fix it.
+        let (rate_limited, settled) = match &resp {
+            Ok(resp) if resp.status == 429 => {
+                let rate_limited = provider.classify(resp).status == KeyStatus::RateLimited;
+                (rate_limited, !rate_limited)
+            }
+            _ => (false, false),
+        };
+        match limiter.record(rate_limited) {
+            Some(RateChange::Slowed(rps)) => {
+                tracing::warn!(
+                    "{} keeps answering 429, slowing to {rps:.2} rps",
+                    provider.name()
+                )
+            }
+            Some(RateChange::Restored(rps)) => {
+                tracing::info!("{} recovered, back to {rps:.2} rps", provider.name())
+            }
+            None => {}
+        }
+        let Some(wait) = retry.delay(attempt, &resp).filter(|_| !settled) else {
+            return to_report(provider, key, resp);
+        };
+        match &resp {
+            Ok(resp) => tracing::debug!("HTTP {}, retrying in {wait:?}", resp.status),
+            Err(err) => tracing::debug!("{err}, retrying in {wait:?}"),
+        }
+        tokio::time::sleep(wait).await;
+        attempt += 1;
+    }
+}
+
+#[instrument(skip_all, fields(provider = provider.name()))]
+pub async fn check<P>(
+    provider: &P,
+    keys: Vec<String>,
+    options: &RunOptions,
+    client: Client,
+) -> anyhow::Result<Vec<KeyReport>>
+where
+    P: Provider + ?Sized,
+{
+    let reports = check_stream(provider, keys, options, client)
+        .collect::<Vec<_>>()
+        .await;
+    Ok(reports)
+}
+
+fn to_report<P>(provider: &P, key: String, resp: Result<Response, ProbeError>) -> KeyReport
+where
+    P: Provider + ?Sized,
+{
+    match resp {
+        Ok(resp) => {
+            let classification = provider.classify(&resp);
+            if let Some(err) = &classification.error {
+                tracing::error!("Error: {}, {err}", RedactedKey::new(&key));
+            }
+            KeyReport {
+                key,
+                provider: provider.name(),
+                status: classification.status,
+                balances: classification.balances,
+                details: classification.details,
+                http_status: Some(resp.status),
+                body: Some(resp.text),
+                error: classification.error,
+                checked_at: SystemTime::now(),
+                buckets: classification.buckets,
+            }
+        }
+        Err(err) => {
+            tracing::error!("Error: {}, {err}", RedactedKey::new(&key));
+            unsent_report(
+                provider.name(),
+                key,
+                KeyStatus::Unknown,
+                provider.error_bucket(),
+                err.to_string(),
+            )
+        }
+    }
+}
@@ -1,13 +1,16 @@
-use std::time::Duration;
-
-use futures::StreamExt;
-use indicatif::ProgressBar;
+use futures::{future::BoxFuture, FutureExt};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use tokio_stream::wrappers::IntervalStream;
-use tracing::instrument;
 
-use crate::save_to_file;
+use crate::{
+    error::ProbeError,
+    provider::{
+        classify_failure, get_with_bearer, with_error_buckets, Classification, Endpoint, Provider,
+        Response,
+    },
+    report::KeyStatus,
+    validate::{KeyFormat, Length, Malformed},
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UserInfo {
@@ -23,101 +26,89 @@ pub struct Data {
     pub total_balance: String,
 }
 
-#[instrument(skip_all)]
-async fn get_userinfo(key: &str, client: Client) -> anyhow::Result<UserInfo> {
-    let url = "https://api.siliconflow.cn/v1/user/info";
-    let resp = client
-        .get(url)
-        .header("Authorization", format!("Bearer {key}"))
-        .send()
-        .await?;
-    let status_code = resp.status();
-    if status_code != reqwest::StatusCode::OK {
-        let err_text = resp.text().await?;
-        return Err(anyhow::anyhow!(err_text));
-    }
-    let user_info = resp.json::<UserInfo>().await?;
-    Ok(user_info)
+const BUCKETS: [&str; 13] = with_error_buckets([
+    "pro_key",
+    "key",
+    "no_balance_keys",
+    "neg_balance_keys",
+    "disable_keys",
+]);
+
+pub struct Siliconflow {
+    endpoint: Endpoint,
 }
 
-#[instrument(skip_all)]
-pub async fn check(keys: Vec<String>, query_per_sec: usize, client: Client) -> anyhow::Result<()> {
-    let bar = ProgressBar::new(keys.len() as u64);
-    let tasks = keys.into_iter().map(|key| {
-        let client = client.clone();
-        let bar = bar.clone();
-        async move {
-            let resp = get_userinfo(&key, client).await;
-            bar.inc(1);
-            (key, resp)
+impl Default for Siliconflow {
+    fn default() -> Self {
+        Self {
+            endpoint: Endpoint::new("siliconflow", "https://api.siliconflow.cn/v1"),
         }
-    });
-    let interval_tick = 1_f64 / (query_per_sec as f64);
-    let interval = tokio::time::interval(Duration::from_secs_f64(interval_tick));
-    let throttled_tasks = IntervalStream::new(interval).zip(futures::stream::iter(tasks));
-    let resp = throttled_tasks
-        .map(|(_, task)| task)
-        .buffer_unordered(query_per_sec)
-        .collect::<Vec<_>>()
-        .await;
-    bar.finish();
-    check_resp(resp).await?;
-    Ok(())
+    }
 }
 
-#[instrument(skip_all)]
-async fn check_resp(resp: Vec<(String, anyhow::Result<UserInfo>)>) -> anyhow::Result<()> {
-    let mut total = 0_f64;
-    let mut total_pro = 0_f64;
-    let mut pro_keys = Vec::new();
-    let mut have_banlance_keys = Vec::new();
-    let mut no_balance_keys = Vec::new();
-    let mut ne_balance_keys = Vec::new();
-    let mut disable_keys = Vec::new();
-    let mut invalid_keys = Vec::new();
-    let mut detail = Vec::new();
-    detail.push("key, charge_balance, total_balance".to_string());
-    for (key, resp) in resp.iter() {
-        match resp {
-            Ok(user) => {
-                if user.data.status == "disable" {
-                    disable_keys.push(key);
-                    continue;
-                }
+impl Provider for Siliconflow {
+    fn name(&self) -> &'static str {
+        "siliconflow"
+    }
 
-                let charge_balance = user.data.charge_balance.parse::<f64>().unwrap_or_default();
-                let total_balance = user.data.total_balance.parse::<f64>().unwrap_or_default();
-                if charge_balance > 0_f64 {
-                    pro_keys.push(key);
-                    total_pro += charge_balance;
-                }
-                match total_balance {
-                    0.5.. => {
-                        have_banlance_keys.push(key);
-                        total += total_balance;
-                    }
-                    0.0..0.5 => {
-                        no_balance_keys.push(key);
-                    }
-                    _ => ne_balance_keys.push(key),
-                }
-                detail.push(format!("{key}, {charge_balance}, {total_balance}"));
-            }
-            Err(err) => {
-                tracing::error!("Error: {key}, {err}");
-                invalid_keys.push(key);
-                continue;
+    fn buckets(&self) -> &'static [&'static str] {
+        &BUCKETS
+    }
+
+    fn endpoint_mut(&mut self) -> &mut Endpoint {
+        &mut self.endpoint
+    }
+
+    fn balance_columns(&self) -> &'static [&'static str] {
+        &["charge_balance", "total_balance"]
+    }
+
+    fn validate(&self, key: &str) -> Result<(), Malformed> {
+        KeyFormat {
+            prefix: "sk-",
+            length: Length::Exact(51),
+            charset: |b| b.is_ascii_lowercase(),
+        }
+        .check(key)
+    }
+
+    fn probe<'a>(
+        &'a self,
+        key: &'a str,
+        client: &'a Client,
+    ) -> BoxFuture<'a, Result<Response, ProbeError>> {
+        get_with_bearer(self.endpoint.url("/user/info"), key, client).boxed()
+    }
+
+    fn classify(&self, resp: &Response) -> Classification {
+        if let Some(classification) = self.endpoint.classify(resp, self.buckets()) {
+            return classification;
+        }
+        if resp.status != 200 {
+            return classify_failure(resp);
+        }
+        let user = match serde_json::from_str::<UserInfo>(&resp.text) {
+            Ok(user) => user,
+            Err(err) => return Classification::failed(ProbeError::decode(resp.status, err)),
+        };
+        if user.data.status == "disable" {
+            return Classification::new(KeyStatus::Disabled, "disable_keys");
+        }
+
+        let charge_balance = user.data.charge_balance.parse::<f64>().unwrap_or_default();
+        let total_balance = user.data.total_balance.parse::<f64>().unwrap_or_default();
+        let mut classification = match total_balance {
+            balance if self.endpoint.rules.is_valid_balance(balance) => {
+                Classification::new(KeyStatus::Valid, "key")
             }
+            0.0.. => Classification::new(KeyStatus::NoBalance, "no_balance_keys"),
+            _ => Classification::new(KeyStatus::NegativeBalance, "neg_balance_keys"),
+        };
+        if charge_balance > 0_f64 {
+            classification.buckets.insert(0, "pro_key");
         }
+        classification
+            .with_balance("charge_balance", charge_balance, Some("CNY"))
+            .with_balance("total_balance", total_balance, Some("CNY"))
     }
-    let prefix = "siliconflow";
-    save_to_file(pro_keys, &format!("{prefix}_pro_key")).await?;
-    save_to_file(have_banlance_keys, &format!("{prefix}_key")).await?;
-    save_to_file(no_balance_keys, &format!("{prefix}_no_balance_keys")).await?;
-    save_to_file(ne_balance_keys, &format!("{prefix}_neg_balance_keys")).await?;
-    save_to_file(disable_keys, &format!("{prefix}_disable_keys")).await?;
-    save_to_file(invalid_keys, &format!("{prefix}_invalid_keys")).await?;
-    save_to_file(detail, &format!("{prefix}_detail.csv")).await?;
-    tracing::info!("total: {total}, pro: {total_pro}");
-    Ok(())
 }
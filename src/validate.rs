@@ -0,0 +1,113 @@
+use std::{collections::HashSet, fmt};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Malformed {
+    Quoted,
+    Whitespace,
+    NonAscii,
+    Prefix(&'static str),
+    Length { expected: usize, actual: usize },
+    TooShort { min: usize, actual: usize },
+    Charset,
+    Separator(char),
+}
+
+impl fmt::Display for Malformed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Malformed::Quoted => f.write_str("contains quotes"),
+            Malformed::Whitespace => f.write_str("contains whitespace"),
+            Malformed::NonAscii => f.write_str("contains non-ASCII characters"),
+            Malformed::Prefix(prefix) => write!(f, "does not start with `{prefix}`"),
+            Malformed::Length { expected, actual } => {
+                write!(f, "expected {expected} characters, got {actual}")
+            }
+            Malformed::TooShort { min, actual } => {
+                write!(f, "expected at least {min} characters, got {actual}")
+            }
+            Malformed::Charset => f.write_str("contains unexpected characters"),
+            Malformed::Separator(separator) => write!(f, "does not contain `{separator}`"),
+        }
+    }
+}
+
+/// Rejects what no provider accepts: quotes, whitespace and non-ASCII text,
+/// usually left over from copying keys out of JSON or chat messages.
+pub fn check_common(key: &str) -> Result<(), Malformed> {
+    if key.contains(['"', '\'', '`']) {
+        return Err(Malformed::Quoted);
+    }
+    if key.contains(char::is_whitespace) {
+        return Err(Malformed::Whitespace);
+    }
+    if !key.is_ascii() {
+        return Err(Malformed::NonAscii);
+    }
+    Ok(())
+}
+
+pub enum Length {
+    Exact(usize),
+    AtLeast(usize),
+}
+
+/// A fixed prefix followed by a body of a known length and character set.
+pub struct KeyFormat {
+    pub prefix: &'static str,
+    pub length: Length,
+    pub charset: fn(u8) -> bool,
+}
+
+impl KeyFormat {
+    pub fn check(&self, key: &str) -> Result<(), Malformed> {
+        check_common(key)?;
+        let body = key
+            .strip_prefix(self.prefix)
+            .ok_or(Malformed::Prefix(self.prefix))?;
+        match self.length {
+            Length::Exact(expected) if key.len() != expected => {
+                return Err(Malformed::Length {
+                    expected,
+                    actual: key.len(),
+                })
+            }
+            Length::AtLeast(min) if key.len() < min => {
+                return Err(Malformed::TooShort {
+                    min,
+                    actual: key.len(),
+                })
+            }
+            _ => {}
+        }
+        if !body.bytes().all(self.charset) {
+            return Err(Malformed::Charset);
+        }
+        Ok(())
+    }
+}
+
+pub fn is_base64url(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'-' || b == b'_'
+}
+
+/// Splits input lines into keys, skipping blanks and `#` comments, and keeps
+/// only the first occurrence of each key. Returns the keys and the repeated
+/// ones, each listed once.
+pub fn parse_keys(contents: &str) -> (Vec<String>, Vec<String>) {
+    let mut keys: Vec<String> = Vec::new();
+    let mut duplicates: Vec<String> = Vec::new();
+    let mut seen = HashSet::new();
+    for key in contents
+        .lines()
+        .map(|k| k.trim())
+        .filter(|k| !k.is_empty())
+        .filter(|k| !k.starts_with("#"))
+    {
+        if seen.insert(key) {
+            keys.push(key.to_string());
+        } else if !duplicates.iter().any(|d| d == key) {
+            duplicates.push(key.to_string());
+        }
+    }
+    (keys, duplicates)
+}
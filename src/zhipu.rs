@@ -0,0 +1,148 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use futures::{future::BoxFuture, FutureExt};
+use hmac::{Hmac, Mac};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::Sha256;
+
+use crate::{
+    client::ClientOptions,
+    error::ProbeError,
+    limiter::Limits,
+    provider::{
+        classify_failure, with_error_buckets, Classification, Endpoint, Provider, Response,
+    },
+    report::KeyStatus,
+    validate::{self, Malformed},
+};
+
+/// Cheapest paid model; free ones keep answering after the account runs
+/// into arrears.
+pub const PROBE_MODEL: &str = "glm-4-air";
+
+/// How long a signed token stays valid.
+const TOKEN_TTL: Duration = Duration::from_secs(180);
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Completion {
+    choices: Vec<serde_json::Value>,
+}
+
+const BUCKETS: [&str; 10] = with_error_buckets(["key", "arrears_keys"]);
+
+pub struct Zhipu {
+    endpoint: Endpoint,
+}
+
+impl Default for Zhipu {
+    fn default() -> Self {
+        Self {
+            endpoint: Endpoint::new("zhipu", "https://open.bigmodel.cn/api/paas/v4"),
+        }
+    }
+}
+
+/// Signs an `id.secret` key into the HS256 JWT Zhipu expects as bearer token.
+/// `key` must pass `Zhipu::validate`.
+pub fn sign_token(key: &str, now: SystemTime) -> String {
+    let (id, secret) = key.split_once('.').unwrap_or((key, ""));
+    let timestamp = now
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    let exp = timestamp + TOKEN_TTL.as_millis() as u64;
+    let header = json!({"alg": "HS256", "sign_type": "SIGN"});
+    let payload = json!({"api_key": id, "exp": exp, "timestamp": timestamp});
+    let message = format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(header.to_string()),
+        URL_SAFE_NO_PAD.encode(payload.to_string())
+    );
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(message.as_bytes());
+    let signature = URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+    format!("{message}.{signature}")
+}
+
+async fn say_hi(base_url: &str, key: &str, client: &Client) -> Result<Response, ProbeError> {
+    let resp = client
+        .post(format!("{base_url}/chat/completions"))
+        .bearer_auth(sign_token(key, SystemTime::now()))
+        .json(&json!({
+            "model": PROBE_MODEL,
+            "messages": [{"role": "user", "content": "hi"}],
+            "max_tokens": 1,
+        }))
+        .send()
+        .await?;
+    Ok(Response::read(resp).await?)
+}
+
+impl Provider for Zhipu {
+    fn name(&self) -> &'static str {
+        "zhipu"
+    }
+
+    fn buckets(&self) -> &'static [&'static str] {
+        &BUCKETS
+    }
+
+    fn endpoint_mut(&mut self) -> &mut Endpoint {
+        &mut self.endpoint
+    }
+
+    /// Every check is a billed generation, and Zhipu answers 1302 to
+    /// accounts running too many at once.
+    fn default_limits(&self) -> Limits {
+        Limits {
+            rps: 1.0,
+            concurrency: 2,
+            burst: 1,
+        }
+    }
+
+    /// Generating even a single token can take a while.
+    fn default_client_options(&self) -> ClientOptions {
+        ClientOptions {
+            timeout: Some(ClientOptions::DEFAULT_TIMEOUT),
+            connect_timeout: Some(ClientOptions::DEFAULT_CONNECT_TIMEOUT),
+            ..ClientOptions::default()
+        }
+    }
+
+    /// Keys are an id and a secret joined by a dot.
+    fn validate(&self, key: &str) -> Result<(), Malformed> {
+        validate::check_common(key)?;
+        let (id, secret) = key.split_once('.').ok_or(Malformed::Separator('.'))?;
+        let alphanumeric = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_alphanumeric());
+        if !alphanumeric(id) || !alphanumeric(secret) {
+            return Err(Malformed::Charset);
+        }
+        Ok(())
+    }
+
+    fn probe<'a>(
+        &'a self,
+        key: &'a str,
+        client: &'a Client,
+    ) -> BoxFuture<'a, Result<Response, ProbeError>> {
+        say_hi(&self.endpoint.base_url, key, client).boxed()
+    }
+
+    fn classify(&self, resp: &Response) -> Classification {
+        if let Some(classification) = self.endpoint.classify(resp, self.buckets()) {
+            return classification;
+        }
+        if resp.status != 200 {
+            return classify_failure(resp);
+        }
+        match serde_json::from_str::<Completion>(&resp.text) {
+            Ok(_) => Classification::new(KeyStatus::Valid, "key"),
+            Err(err) => Classification::failed(ProbeError::decode(resp.status, err)),
+        }
+    }
+}
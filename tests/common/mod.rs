@@ -0,0 +1,601 @@
+//! A local server imitating every provider's API, and helpers to run the CLI
+//! against it.
+
+#![allow(dead_code)]
+
+use std::{
+    path::Path,
+    process::{Command, Output},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use tempfile::TempDir;
+use wiremock::{
+    matchers::{header, method, path, query_param},
+    Match, Mock, MockServer, Request, ResponseTemplate,
+};
+
+pub const GEMINI_MODEL: &str = "gemini-2.0-flash";
+
+/// Every provider the server imitates.
+pub const PROVIDERS: &[&str] = &[
+    "deepseek",
+    "siliconflow",
+    "openrouter",
+    "ppinfra",
+    "gemini",
+    "openai",
+    "anthropic",
+    "moonshot",
+    "zhipu",
+    "dashscope",
+];
+
+/// How long the `Slow` scenario takes to answer.
+pub const SLOW: Duration = Duration::from_millis(1500);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scenario {
+    Valid,
+    ZeroBalance,
+    NegativeBalance,
+    Disabled,
+    Unauthorized,
+    Leaked,
+    Location,
+    QuotaExceeded,
+    ServerError,
+    Slow,
+    RateLimited,
+    /// Answers 503 once, then like `Valid`.
+    Flaky,
+    /// Answers 429 once, then like `Valid`.
+    Throttled,
+}
+
+impl Scenario {
+    pub const ALL: [Scenario; 13] = [
+        Scenario::Valid,
+        Scenario::ZeroBalance,
+        Scenario::NegativeBalance,
+        Scenario::Disabled,
+        Scenario::Unauthorized,
+        Scenario::Leaked,
+        Scenario::Location,
+        Scenario::QuotaExceeded,
+        Scenario::ServerError,
+        Scenario::Slow,
+        Scenario::RateLimited,
+        Scenario::Flaky,
+        Scenario::Throttled,
+    ];
+
+    fn id(self) -> usize {
+        Self::ALL.iter().position(|s| *s == self).unwrap() + 1
+    }
+}
+
+/// A key in `provider`'s format that the mock server answers with `scenario`.
+pub fn key(provider: &str, scenario: Scenario) -> String {
+    let id = scenario.id();
+    match provider {
+        "deepseek" => format!("sk-{id:032x}"),
+        "siliconflow" => {
+            let letter = char::from(b'a' + id as u8);
+            format!("sk-{}", letter.to_string().repeat(48))
+        }
+        "openrouter" => format!("sk-or-v1-{id:064x}"),
+        "ppinfra" => format!("sk_mock{id:020}"),
+        "gemini" => format!("AIza{id:035}"),
+        "openai" => format!("sk-proj-{id:064}"),
+        "anthropic" => format!("sk-ant-api03-{id:080}"),
+        "moonshot" => format!("sk-Moon{id:044}"),
+        "zhipu" => format!("{id:032x}.MockSecret{id:06}"),
+        "dashscope" => format!("sk-da{id:030x}"),
+        _ => panic!("unknown provider {provider}"),
+    }
+}
+
+pub struct MockProviders {
+    pub server: MockServer,
+}
+
+impl MockProviders {
+    /// Starts the server with every provider and scenario mounted. Each
+    /// provider lives under its own path prefix, e.g. `/deepseek/user/balance`.
+    pub async fn start() -> Self {
+        let server = MockServer::start().await;
+        // Keys a provider does not know get a 401, as from the real APIs, so
+        // `auto` can move on to the next provider sharing the key format.
+        for route in [
+            "/deepseek/user/balance",
+            "/siliconflow/user/info",
+            "/openrouter/credits",
+            "/ppinfra/v3/user",
+            "/moonshot/users/me/balance",
+            "/openai/models",
+            "/dashscope/models",
+        ] {
+            Mock::given(method("GET"))
+                .and(path(route))
+                .respond_with(
+                    ResponseTemplate::new(401)
+                        .set_body_json(json!({"error": {"message": "Authentication Fails"}})),
+                )
+                .with_priority(u8::MAX)
+                .mount(&server)
+                .await;
+        }
+        for scenario in Scenario::ALL {
+            for (provider, route) in [
+                ("deepseek", "/user/balance"),
+                ("siliconflow", "/user/info"),
+                ("openrouter", "/credits"),
+                ("ppinfra", "/v3/user"),
+                ("moonshot", "/users/me/balance"),
+            ] {
+                let Some(body) = balance_body(provider, scenario) else {
+                    continue;
+                };
+                let bearer = format!("Bearer {}", key(provider, scenario));
+                if let Some(template) = first_answer(scenario) {
+                    Mock::given(method("GET"))
+                        .and(path(format!("/{provider}{route}")))
+                        .and(header("authorization", bearer.as_str()))
+                        .respond_with(template)
+                        .up_to_n_times(1)
+                        .with_priority(1)
+                        .mount(&server)
+                        .await;
+                }
+                Mock::given(method("GET"))
+                    .and(path(format!("/{provider}{route}")))
+                    .and(header("authorization", bearer.as_str()))
+                    .respond_with(respond(scenario, body))
+                    .mount(&server)
+                    .await;
+            }
+            if let Some(template) = openai_response(scenario) {
+                let bearer = format!("Bearer {}", key("openai", scenario));
+                Mock::given(method("GET"))
+                    .and(path("/openai/models"))
+                    .and(header("authorization", bearer.as_str()))
+                    .respond_with(template)
+                    .mount(&server)
+                    .await;
+            }
+            if let Some(template) = anthropic_response(scenario) {
+                Mock::given(method("GET"))
+                    .and(path("/anthropic/models"))
+                    .and(query_param("limit", "1000"))
+                    .and(header("x-api-key", key("anthropic", scenario).as_str()))
+                    .and(header("anthropic-version", "2023-06-01"))
+                    .respond_with(template)
+                    .mount(&server)
+                    .await;
+            }
+            if let Some(template) = zhipu_response(scenario) {
+                Mock::given(method("POST"))
+                    .and(path("/zhipu/chat/completions"))
+                    .and(ZhipuToken(key("zhipu", scenario)))
+                    .respond_with(template)
+                    .mount(&server)
+                    .await;
+            }
+            if let Some(template) = dashscope_response(scenario) {
+                let bearer = format!("Bearer {}", key("dashscope", scenario));
+                Mock::given(method("GET"))
+                    .and(path("/dashscope/models"))
+                    .and(header("authorization", bearer.as_str()))
+                    .respond_with(template)
+                    .mount(&server)
+                    .await;
+            }
+            if let Some(body) = gemini_body(scenario) {
+                Mock::given(method("POST"))
+                    .and(path(format!(
+                        "/gemini/models/{GEMINI_MODEL}:generateContent"
+                    )))
+                    .and(header("x-goog-api-key", key("gemini", scenario).as_str()))
+                    .respond_with(respond(scenario, body))
+                    .mount(&server)
+                    .await;
+            }
+        }
+        Self { server }
+    }
+
+    /// `--base-url` arguments pointing every provider at this server.
+    pub fn base_url_args(&self) -> Vec<String> {
+        PROVIDERS
+            .iter()
+            .flat_map(|provider| {
+                [
+                    "--base-url".to_string(),
+                    format!("{provider}={}/{provider}", self.server.uri()),
+                ]
+            })
+            .collect()
+    }
+
+    /// Runs `aicheck [args] <keys file> <command>` in a fresh directory with
+    /// bucket output, and retries and requeues turned off unless `args` set
+    /// them.
+    pub fn run(&self, keys: &[String], args: &[&str], command: &[&str]) -> Run {
+        let dir = tempfile::tempdir().unwrap();
+        let keys_file = dir.path().join("keys.txt");
+        std::fs::write(&keys_file, keys.join("\n")).unwrap();
+        let defaults = [["--max-attempts", "1"], ["--max-requeues", "0"]]
+            .into_iter()
+            .filter(|[flag, _]| !args.contains(flag))
+            .flatten();
+        let output = Command::new(env!("CARGO_BIN_EXE_aicheck"))
+            .current_dir(dir.path())
+            .env("XDG_CONFIG_HOME", dir.path())
+            .args(self.base_url_args())
+            .args(defaults)
+            .args(args)
+            .arg(&keys_file)
+            .args(command)
+            .output()
+            .unwrap();
+        Run { dir, output }
+    }
+}
+
+/// Runs one key of `provider` per scenario through `command` and checks each
+/// key landed in its bucket, and only there with keys sharing the bucket.
+pub async fn assert_buckets(
+    provider: &str,
+    command: &[&str],
+    scenarios: &[(Scenario, &str)],
+) -> Run {
+    let mock = MockProviders::start().await;
+    let keys = scenarios
+        .iter()
+        .map(|(scenario, _)| key(provider, *scenario))
+        .collect::<Vec<_>>();
+
+    let run = mock.run(&keys, &[], command);
+    run.assert_success();
+
+    for (scenario, bucket) in scenarios {
+        let mut expected = scenarios
+            .iter()
+            .filter(|(_, other)| other == bucket)
+            .map(|(scenario, _)| key(provider, *scenario))
+            .collect::<Vec<_>>();
+        expected.sort();
+        let mut found = run.bucket(bucket);
+        found.sort();
+        assert_eq!(found, expected, "{scenario:?}");
+    }
+    run
+}
+
+pub struct Run {
+    pub dir: TempDir,
+    pub output: Output,
+}
+
+impl Run {
+    pub fn assert_success(&self) -> &Self {
+        assert!(
+            self.output.status.success(),
+            "aicheck failed: {}",
+            String::from_utf8_lossy(&self.output.stderr)
+        );
+        self
+    }
+
+    /// Keys written to the bucket file `name`, e.g. `deepseek_key`.
+    pub fn bucket(&self, name: &str) -> Vec<String> {
+        read_lines(&self.dir.path().join(name))
+    }
+
+    pub fn stdout(&self) -> String {
+        String::from_utf8_lossy(&self.output.stdout).into_owned()
+    }
+}
+
+pub fn read_lines(path: &Path) -> Vec<String> {
+    let contents =
+        std::fs::read_to_string(path).unwrap_or_else(|err| panic!("{}: {err}", path.display()));
+    contents.lines().map(String::from).collect()
+}
+
+fn respond(scenario: Scenario, body: Value) -> ResponseTemplate {
+    let status = match scenario {
+        Scenario::Unauthorized => 401,
+        Scenario::Leaked => 403,
+        Scenario::Location => 400,
+        Scenario::QuotaExceeded => 429,
+        Scenario::ServerError => 500,
+        _ => 200,
+    };
+    let template = ResponseTemplate::new(status).set_body_json(body);
+    match scenario {
+        Scenario::Slow => template.set_delay(SLOW),
+        _ => template,
+    }
+}
+
+/// What the balance providers answer the first request of a key with, before
+/// the regular answer of `scenario`.
+fn first_answer(scenario: Scenario) -> Option<ResponseTemplate> {
+    match scenario {
+        Scenario::Flaky => {
+            Some(ResponseTemplate::new(503).set_body_json(json!({"error": "service unavailable"})))
+        }
+        Scenario::Throttled => Some(
+            ResponseTemplate::new(429)
+                .insert_header("retry-after", "0")
+                .set_body_json(json!({"error": "too many requests"})),
+        ),
+        _ => None,
+    }
+}
+
+fn balance_body(provider: &str, scenario: Scenario) -> Option<Value> {
+    let balance = match scenario {
+        Scenario::Disabled if provider != "siliconflow" => return None,
+        Scenario::Valid
+        | Scenario::Slow
+        | Scenario::Disabled
+        | Scenario::Flaky
+        | Scenario::Throttled => "12.5",
+        Scenario::ZeroBalance => "0",
+        Scenario::NegativeBalance => "-3.2",
+        Scenario::Unauthorized => {
+            return Some(json!({"error": {"message": "Authentication Fails", "code": 401}}))
+        }
+        Scenario::ServerError => return Some(json!({"error": "internal error"})),
+        _ => return None,
+    };
+    let amount = balance.parse::<f64>().unwrap();
+    let body = match provider {
+        "deepseek" => json!({
+            "is_available": amount > 0.0,
+            "balance_infos": [{
+                "currency": "CNY",
+                "total_balance": balance,
+                "granted_balance": "0.00",
+                "topped_up_balance": balance,
+            }],
+        }),
+        "siliconflow" => json!({
+            "code": 20000,
+            "message": "OK",
+            "data": {
+                "balance": balance,
+                "status": if scenario == Scenario::Disabled { "disable" } else { "normal" },
+                "chargeBalance": "0.00",
+                "totalBalance": balance,
+            },
+        }),
+        "openrouter" => json!({"data": {"total_credits": amount, "total_usage": 0.0}}),
+        "ppinfra" => json!({"credit_balance": amount}),
+        "moonshot" => json!({
+            "code": 0,
+            "data": {
+                "available_balance": amount,
+                "voucher_balance": 0.0,
+                "cash_balance": amount,
+            },
+            "scode": "0x0",
+            "status": true,
+        }),
+        _ => unreachable!(),
+    };
+    Some(body)
+}
+
+/// OpenAI answers 429 for both an exhausted quota and a too high rate.
+fn openai_response(scenario: Scenario) -> Option<ResponseTemplate> {
+    let error = |status: u16, code: &str, message: &str| {
+        ResponseTemplate::new(status).set_body_json(json!({
+            "error": {"message": message, "type": code, "param": null, "code": code},
+        }))
+    };
+    let template = match scenario {
+        Scenario::Valid => ResponseTemplate::new(200)
+            .insert_header("openai-organization", "org-mock")
+            .insert_header("openai-project", "proj_mock")
+            .set_body_json(json!({
+                "object": "list",
+                "data": [
+                    {"id": "gpt-4o", "object": "model", "owned_by": "system"},
+                    {"id": "gpt-4o-mini", "object": "model", "owned_by": "system"},
+                ],
+            })),
+        Scenario::ZeroBalance => error(
+            429,
+            "insufficient_quota",
+            "You exceeded your current quota, please check your plan and billing details.",
+        ),
+        Scenario::RateLimited => error(
+            429,
+            "rate_limit_exceeded",
+            "Rate limit reached for requests",
+        ),
+        Scenario::Unauthorized => error(401, "invalid_api_key", "Incorrect API key provided."),
+        Scenario::ServerError => error(500, "server_error", "The server had an error."),
+        _ => return None,
+    };
+    Some(template)
+}
+
+fn anthropic_response(scenario: Scenario) -> Option<ResponseTemplate> {
+    let error = |status: u16, kind: &str, message: &str| {
+        ResponseTemplate::new(status).set_body_json(json!({
+            "type": "error",
+            "error": {"type": kind, "message": message},
+        }))
+    };
+    let template = match scenario {
+        Scenario::Valid => ResponseTemplate::new(200)
+            .insert_header("anthropic-organization-id", "org-mock")
+            .set_body_json(json!({
+                "data": [
+                    {"type": "model", "id": "claude-sonnet-4-5", "display_name": "Claude Sonnet 4.5"},
+                    {"type": "model", "id": "claude-haiku-4-5", "display_name": "Claude Haiku 4.5"},
+                ],
+                "has_more": false,
+            })),
+        Scenario::ZeroBalance => error(
+            400,
+            "invalid_request_error",
+            "Your credit balance is too low to access the Anthropic API. Please go to Plans & Billing to upgrade or purchase credits.",
+        ),
+        Scenario::Disabled => error(
+            403,
+            "permission_error",
+            "Your API key does not have permission to use the specified resource.",
+        ),
+        Scenario::Unauthorized => error(401, "authentication_error", "invalid x-api-key"),
+        Scenario::RateLimited => error(429, "rate_limit_error", "Number of requests has exceeded your rate limit."),
+        Scenario::ServerError => error(500, "api_error", "Internal server error"),
+        _ => return None,
+    };
+    Some(template)
+}
+
+/// Matches requests whose bearer token is a live JWT signed with `.0`.
+struct ZhipuToken(String);
+
+impl Match for ZhipuToken {
+    fn matches(&self, request: &Request) -> bool {
+        let Some((id, secret)) = self.0.split_once('.') else {
+            return false;
+        };
+        let Some(token) = request
+            .headers
+            .get("authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+        else {
+            return false;
+        };
+        let Some((message, signature)) = token.rsplit_once('.') else {
+            return false;
+        };
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(message.as_bytes());
+        let signed = URL_SAFE_NO_PAD
+            .decode(signature)
+            .is_ok_and(|signature| mac.verify_slice(&signature).is_ok());
+        let decode = |part: &str| {
+            let json = URL_SAFE_NO_PAD.decode(part).ok()?;
+            serde_json::from_slice::<Value>(&json).ok()
+        };
+        let (Some(header), Some(payload)) = message
+            .split_once('.')
+            .map_or((None, None), |(h, p)| (decode(h), decode(p)))
+        else {
+            return false;
+        };
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64;
+        signed
+            && header["alg"] == "HS256"
+            && header["sign_type"] == "SIGN"
+            && payload["api_key"] == id
+            && payload["exp"].as_u64().is_some_and(|exp| exp > now)
+    }
+}
+
+fn zhipu_response(scenario: Scenario) -> Option<ResponseTemplate> {
+    let error = |status: u16, code: &str, message: &str| {
+        ResponseTemplate::new(status)
+            .set_body_json(json!({"error": {"code": code, "message": message}}))
+    };
+    let template = match scenario {
+        Scenario::Valid => ResponseTemplate::new(200).set_body_json(json!({
+            "id": "mock",
+            "model": "glm-4-air",
+            "choices": [{"index": 0, "finish_reason": "length", "message": {"role": "assistant", "content": "你"}}],
+            "usage": {"prompt_tokens": 6, "completion_tokens": 1, "total_tokens": 7},
+        })),
+        Scenario::Unauthorized => error(401, "1000", "身份验证失败。"),
+        Scenario::NegativeBalance => error(429, "1113", "您的账户已欠费，请充值后重试。"),
+        Scenario::RateLimited => error(429, "1302", "您当前使用该API的并发数过高，请降低并发，或联系客服增加限额。"),
+        _ => return None,
+    };
+    Some(template)
+}
+
+/// Errors in the native API's shape, which the compatible mode passes on for
+/// account problems.
+fn dashscope_response(scenario: Scenario) -> Option<ResponseTemplate> {
+    let error = |status: u16, code: &str, message: &str| {
+        ResponseTemplate::new(status).set_body_json(json!({
+            "code": code,
+            "message": message,
+            "request_id": "00000000-0000-0000-0000-000000000000",
+        }))
+    };
+    let template = match scenario {
+        Scenario::Valid => ResponseTemplate::new(200).set_body_json(json!({
+            "object": "list",
+            "data": [
+                {"id": "qwen-plus", "object": "model", "owned_by": "system"},
+                {"id": "qwen-turbo", "object": "model", "owned_by": "system"},
+                {"id": "qwen-max", "object": "model", "owned_by": "system"},
+            ],
+        })),
+        Scenario::NegativeBalance => error(
+            400,
+            "Arrearage",
+            "Access denied, please make sure your account is in good standing.",
+        ),
+        Scenario::Unauthorized => ResponseTemplate::new(401).set_body_json(json!({
+            "error": {
+                "message": "Incorrect API key provided.",
+                "type": "invalid_request_error",
+                "param": null,
+                "code": "invalid_api_key",
+            },
+        })),
+        Scenario::Disabled => error(400, "InvalidApiKey", "Invalid API-key provided."),
+        _ => return None,
+    };
+    Some(template)
+}
+
+fn gemini_body(scenario: Scenario) -> Option<Value> {
+    let error = |code: u16, status: &str, message: &str| json!({"error": {"code": code, "message": message, "status": status}});
+    let body = match scenario {
+        Scenario::Valid | Scenario::Slow => json!({
+            "candidates": [{"content": {"parts": [{"text": "Hi there!"}], "role": "model"}}],
+        }),
+        Scenario::Unauthorized => error(
+            401,
+            "UNAUTHENTICATED",
+            "API keys are not supported by this API.",
+        ),
+        Scenario::Leaked => error(
+            403,
+            "PERMISSION_DENIED",
+            "Your API key was reported as leaked. Please use another API key.",
+        ),
+        Scenario::Location => error(
+            400,
+            "FAILED_PRECONDITION",
+            "User location is not supported for the API use.",
+        ),
+        Scenario::QuotaExceeded => error(
+            429,
+            "RESOURCE_EXHAUSTED",
+            "Quota exceeded for quota metric 'Generate Content API requests per minute' and limit 'GenerateContent request limit per minute for a region' of service 'generativelanguage.googleapis.com'",
+        ),
+        Scenario::ServerError => error(500, "INTERNAL", "An internal error has occurred."),
+        _ => return None,
+    };
+    Some(body)
+}
@@ -0,0 +1,589 @@
+mod common;
+
+use common::{assert_buckets, key, read_lines, MockProviders, Scenario, GEMINI_MODEL, PROVIDERS};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn deepseek_buckets() {
+    let mock = MockProviders::start().await;
+    let valid = key("deepseek", Scenario::Valid);
+    let zero = key("deepseek", Scenario::ZeroBalance);
+    let unauthorized = key("deepseek", Scenario::Unauthorized);
+    let server_error = key("deepseek", Scenario::ServerError);
+    let slow = key("deepseek", Scenario::Slow);
+    let keys = [&valid, &zero, &unauthorized, &server_error, &slow].map(String::clone);
+
+    let run = mock.run(&keys, &[], &["deepseek"]);
+    run.assert_success();
+
+    let mut valid_keys = run.bucket("deepseek_key");
+    valid_keys.sort();
+    let mut expected = vec![valid, slow];
+    expected.sort();
+    assert_eq!(valid_keys, expected);
+    assert_eq!(run.bucket("deepseek_no_balance_keys"), [zero]);
+    assert_eq!(run.bucket("deepseek_invalid_keys"), [unauthorized]);
+    assert_eq!(run.bucket("deepseek_server_err_keys"), [server_error]);
+    assert!(run.bucket("deepseek_failed_keys").is_empty());
+    assert_eq!(run.bucket("deepseek_detail.csv").len(), keys.len() + 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn server_errors_are_retried() {
+    let flaky = [key("deepseek", Scenario::Flaky)];
+
+    let once = MockProviders::start().await.run(&flaky, &[], &["deepseek"]);
+    once.assert_success();
+    assert_eq!(once.bucket("deepseek_server_err_keys"), flaky);
+
+    let retried = MockProviders::start()
+        .await
+        .run(&flaky, &["--max-attempts", "2"], &["deepseek"]);
+    retried.assert_success();
+    assert_eq!(retried.bucket("deepseek_key"), flaky);
+    assert!(retried.bucket("deepseek_server_err_keys").is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rate_limited_keys_are_requeued() {
+    let throttled = [key("deepseek", Scenario::Throttled)];
+
+    let once = MockProviders::start()
+        .await
+        .run(&throttled, &[], &["deepseek"]);
+    once.assert_success();
+    assert_eq!(once.bucket("deepseek_429_keys"), throttled);
+
+    let requeued =
+        MockProviders::start()
+            .await
+            .run(&throttled, &["--max-requeues", "1"], &["deepseek"]);
+    requeued.assert_success();
+    assert_eq!(requeued.bucket("deepseek_key"), throttled);
+    assert!(requeued.bucket("deepseek_429_keys").is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn siliconflow_buckets() {
+    assert_buckets(
+        "siliconflow",
+        &["siliconflow"],
+        &[
+            (Scenario::Valid, "siliconflow_key"),
+            (Scenario::ZeroBalance, "siliconflow_no_balance_keys"),
+            (Scenario::NegativeBalance, "siliconflow_neg_balance_keys"),
+            (Scenario::Disabled, "siliconflow_disable_keys"),
+            (Scenario::Unauthorized, "siliconflow_invalid_keys"),
+        ],
+    )
+    .await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn moonshot_buckets() {
+    let run = assert_buckets(
+        "moonshot",
+        &["moonshot"],
+        &[
+            (Scenario::Valid, "moonshot_key"),
+            (Scenario::ZeroBalance, "moonshot_no_balance_keys"),
+            (Scenario::NegativeBalance, "moonshot_neg_balance_keys"),
+            (Scenario::Unauthorized, "moonshot_invalid_keys"),
+        ],
+    )
+    .await;
+
+    let detail = run.bucket("moonshot_detail.csv");
+    assert!(detail[0].contains(",available_balance,voucher_balance,cash_balance,currency,"));
+    let negative = key("moonshot", Scenario::NegativeBalance);
+    let negative = detail
+        .iter()
+        .find(|row| row.starts_with(&negative))
+        .unwrap();
+    assert!(negative.contains(",-3.2,0,-3.2,CNY,"), "{negative}");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn openrouter_and_ppinfra_buckets() {
+    for (provider, command) in [("openrouter", "open-router"), ("ppinfra", "ppinfra")] {
+        assert_buckets(
+            provider,
+            &[command],
+            &[
+                (Scenario::Valid, &format!("{provider}_key")),
+                (
+                    Scenario::ZeroBalance,
+                    &format!("{provider}_no_balance_keys"),
+                ),
+                (Scenario::Unauthorized, &format!("{provider}_invalid_keys")),
+            ],
+        )
+        .await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn gemini_buckets() {
+    let run = assert_buckets(
+        "gemini",
+        &["gemini", "--model", GEMINI_MODEL],
+        &[
+            (Scenario::Valid, "gemini_key"),
+            (Scenario::Leaked, "gemini_gemma3_only_keys"),
+            (Scenario::Location, "gemini_location_err_key"),
+            (Scenario::QuotaExceeded, "gemini_invalid_keys"),
+            (Scenario::Unauthorized, "gemini_invalid_keys"),
+            (Scenario::ServerError, "gemini_unknow_err_key"),
+        ],
+    )
+    .await;
+
+    assert!(run.bucket("gemini_429_keys").is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn auto_routes_mixed_keys() {
+    let mock = MockProviders::start().await;
+    let keys = [
+        key("deepseek", Scenario::Valid),
+        key("siliconflow", Scenario::Valid),
+        key("openrouter", Scenario::Valid),
+        key("ppinfra", Scenario::Valid),
+        key("gemini", Scenario::Valid),
+        key("openai", Scenario::Valid),
+        key("anthropic", Scenario::Valid),
+        key("moonshot", Scenario::Valid),
+        key("zhipu", Scenario::Valid),
+        key("dashscope", Scenario::Valid),
+        key("deepseek", Scenario::Unauthorized),
+        key("siliconflow", Scenario::Unauthorized),
+        key("openrouter", Scenario::Unauthorized),
+        "not-a-key".to_string(),
+        "\"sk-quoted\"".to_string(),
+        format!("sk-{}", "a".repeat(47)),
+    ];
+
+    let run = mock.run(&keys, &[], &["auto", "--model", GEMINI_MODEL]);
+    run.assert_success();
+
+    for (provider, key) in PROVIDERS.iter().zip(&keys) {
+        assert_eq!(run.bucket(&format!("{provider}_key")), [key.as_str()]);
+    }
+    let mut invalid = run.bucket("auto_invalid_keys");
+    invalid.sort();
+    let mut expected = vec![keys[10].clone(), keys[11].clone()];
+    expected.sort();
+    assert_eq!(invalid, expected);
+    assert!(run.bucket("deepseek_invalid_keys").is_empty());
+    assert!(run.bucket("dashscope_invalid_keys").is_empty());
+    assert!(run.bucket("openai_invalid_keys").is_empty());
+    assert_eq!(run.bucket("openrouter_invalid_keys"), [keys[12].as_str()]);
+    assert_eq!(run.bucket("auto_unrecognized_keys"), [keys[13].as_str()]);
+    let mut malformed = run.bucket("auto_malformed_keys");
+    malformed.sort();
+    let mut expected = vec![keys[14].clone(), keys[15].clone()];
+    expected.sort();
+    assert_eq!(malformed, expected);
+    assert!(run.bucket("deepseek_malformed_keys").is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn auto_writes_every_bucket() {
+    let mock = MockProviders::start().await;
+    let keys = [key("deepseek", Scenario::Valid)];
+
+    let run = mock.run(&keys, &[], &["auto"]);
+    run.assert_success();
+
+    for bucket in ["invalid_keys", "unrecognized_keys", "malformed_keys"] {
+        assert!(run.bucket(&format!("auto_{bucket}")).is_empty(), "{bucket}");
+    }
+    assert!(run.bucket("deepseek_no_balance_keys").is_empty());
+    assert!(!run.dir.path().join("deepseek_detail.csv").exists());
+    assert!(!run.dir.path().join("siliconflow_key").exists());
+
+    let detail = run.bucket("auto_detail.csv");
+    assert_eq!(detail.len(), 2);
+    assert!(detail[0].starts_with("provider,key,"), "{}", detail[0]);
+    assert!(detail[1].starts_with(&format!("deepseek,{},200,valid,", keys[0])));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn duplicates_are_reported() {
+    let mock = MockProviders::start().await;
+    let valid = key("deepseek", Scenario::Valid);
+    let zero = key("deepseek", Scenario::ZeroBalance);
+    let keys = [&valid, &zero, &valid, &valid].map(String::clone);
+
+    let run = mock.run(&keys, &[], &["deepseek"]);
+    run.assert_success();
+    assert_eq!(run.bucket("deepseek_key"), [valid.as_str()]);
+    assert_eq!(run.bucket("input_duplicate_keys"), [valid.as_str()]);
+
+    let run = mock.run(&[zero.clone(), zero], &["-f", "ndjson"], &["deepseek"]);
+    run.assert_success();
+    let statuses = run
+        .stdout()
+        .lines()
+        .map(|line| {
+            serde_json::from_str::<serde_json::Value>(line).unwrap()["classification"].clone()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(statuses, ["duplicate", "no_balance"]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn output_options_must_fit_the_format() {
+    let mock = MockProviders::start().await;
+    let keys = [key("deepseek", Scenario::Valid)];
+    for (args, message) in [
+        (&["-o", "out.txt"][..], "--output only applies"),
+        (&["-f", "table", "-o", "out.txt"], "--output only applies"),
+        (&["-f", "csv", "-t"], "--timestamped only applies"),
+    ] {
+        let run = mock.run(&keys, args, &["deepseek"]);
+        assert!(!run.output.status.success(), "{args:?}");
+        let stderr = String::from_utf8_lossy(&run.output.stderr);
+        assert!(stderr.contains(message), "{stderr}");
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ndjson_masks_keys_on_stdout() {
+    let mock = MockProviders::start().await;
+    let valid = key("deepseek", Scenario::Valid);
+
+    let run = mock.run(
+        std::slice::from_ref(&valid),
+        &["-f", "ndjson"],
+        &["deepseek"],
+    );
+    run.assert_success();
+
+    let stdout = run.stdout();
+    let report: serde_json::Value = serde_json::from_str(stdout.trim()).unwrap();
+    assert_eq!(report["classification"], "valid");
+    assert_eq!(report["http_status"], 200);
+    assert!(!stdout.contains(&valid));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rules_file_comes_before_builtin_rules() {
+    let mock = MockProviders::start().await;
+    let rules = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(
+        rules.path(),
+        r#"
+[siliconflow]
+valid_balance = 100
+
+[[deepseek.rules]]
+status = [500]
+pointer = "/error"
+equals = "internal error"
+classification = "invalid"
+bucket = "invalid_keys"
+
+[[gemini.rules]]
+status = [403]
+regex = "reported as (leaked|compromised)"
+classification = "invalid"
+bucket = "invalid_keys"
+"#,
+    )
+    .unwrap();
+    let rules = rules.path().to_str().unwrap();
+
+    let valid = key("siliconflow", Scenario::Valid);
+    let run = mock.run(
+        std::slice::from_ref(&valid),
+        &["--rules", rules],
+        &["siliconflow"],
+    );
+    run.assert_success();
+    assert_eq!(run.bucket("siliconflow_no_balance_keys"), [valid.as_str()]);
+
+    let server_error = key("deepseek", Scenario::ServerError);
+    let run = mock.run(
+        std::slice::from_ref(&server_error),
+        &["--rules", rules],
+        &["deepseek"],
+    );
+    run.assert_success();
+    assert_eq!(run.bucket("deepseek_invalid_keys"), [server_error.as_str()]);
+
+    let leaked = key("gemini", Scenario::Leaked);
+    let location = key("gemini", Scenario::Location);
+    let keys = [leaked.clone(), location.clone()];
+    let run = mock.run(
+        &keys,
+        &["--rules", rules],
+        &["gemini", "--model", GEMINI_MODEL],
+    );
+    run.assert_success();
+    assert_eq!(run.bucket("gemini_invalid_keys"), [leaked.as_str()]);
+    assert_eq!(run.bucket("gemini_location_err_key"), [location.as_str()]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rules_with_unknown_buckets_are_rejected() {
+    let mock = MockProviders::start().await;
+    let rules = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(
+        rules.path(),
+        "[[deepseek.rules]]\nclassification = \"invalid\"\nbucket = \"revoked_keys\"\n",
+    )
+    .unwrap();
+
+    let keys = [key("deepseek", Scenario::Valid)];
+    let run = mock.run(
+        &keys,
+        &["--rules", rules.path().to_str().unwrap()],
+        &["deepseek"],
+    );
+
+    assert!(!run.output.status.success());
+    assert!(String::from_utf8_lossy(&run.output.stderr).contains("revoked_keys"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rules_for_unknown_providers_are_rejected() {
+    let mock = MockProviders::start().await;
+    let rules = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(
+        rules.path(),
+        "[[deepsek.rules]]\nclassification = \"invalid\"\nbucket = \"invalid_keys\"\n",
+    )
+    .unwrap();
+
+    let keys = [key("deepseek", Scenario::Valid)];
+    let run = mock.run(
+        &keys,
+        &["--rules", rules.path().to_str().unwrap()],
+        &["deepseek"],
+    );
+
+    assert!(!run.output.status.success());
+    let stderr = String::from_utf8_lossy(&run.output.stderr);
+    assert!(stderr.contains("unknown provider `deepsek`"), "{stderr}");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn config_profile_overrides_top_level_settings() {
+    let mock = MockProviders::start().await;
+    let config = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(
+        config.path(),
+        r#"
+output_dir = "results"
+
+[providers.siliconflow]
+rps = 10.0
+timeout = "5s"
+
+[profile.strict]
+output_dir = "strict"
+
+[profile.strict.providers.siliconflow]
+valid_balance = 100
+"#,
+    )
+    .unwrap();
+    let config = config.path().to_str().unwrap();
+    let valid = key("siliconflow", Scenario::Valid);
+    let keys = std::slice::from_ref(&valid);
+
+    let run = mock.run(keys, &["--config", config], &["siliconflow"]);
+    run.assert_success();
+    let results = run.dir.path().join("results");
+    assert_eq!(
+        read_lines(&results.join("siliconflow_key")),
+        [valid.as_str()]
+    );
+
+    let run = mock.run(
+        keys,
+        &["--config", config, "--profile", "strict"],
+        &["siliconflow"],
+    );
+    run.assert_success();
+    let strict = run.dir.path().join("strict");
+    assert_eq!(
+        read_lines(&strict.join("siliconflow_no_balance_keys")),
+        [valid.as_str()]
+    );
+
+    let run = mock.run(
+        keys,
+        &["--config", config, "--profile", "missing"],
+        &["siliconflow"],
+    );
+    assert!(!run.output.status.success());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn valid_balance_applies_to_every_balance_provider() {
+    let mock = MockProviders::start().await;
+    let config = tempfile::NamedTempFile::new().unwrap();
+    let config_path = config.path().to_str().unwrap();
+    for (provider, command) in [
+        ("deepseek", "deepseek"),
+        ("openrouter", "open-router"),
+        ("ppinfra", "ppinfra"),
+        ("moonshot", "moonshot"),
+        ("siliconflow", "siliconflow"),
+    ] {
+        std::fs::write(
+            config.path(),
+            format!("[providers.{provider}]\nvalid_balance = 20\n"),
+        )
+        .unwrap();
+        let keys = [key(provider, Scenario::Valid)];
+
+        let run = mock.run(&keys, &["--config", config_path], &[command]);
+        run.assert_success();
+        assert_eq!(
+            run.bucket(&format!("{provider}_no_balance_keys")),
+            keys,
+            "{provider}"
+        );
+    }
+
+    std::fs::write(config.path(), "[providers.openai]\nvalid_balance = 20\n").unwrap();
+    let run = mock.run(&[], &["--config", config_path], &["deepseek"]);
+    assert!(!run.output.status.success());
+    let stderr = String::from_utf8_lossy(&run.output.stderr);
+    assert!(stderr.contains("openai reports no balance"), "{stderr}");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn client_options_from_config_and_command_line() {
+    let mock = MockProviders::start().await;
+    let config = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(
+        config.path(),
+        "[providers.deepseek]\ntimeout = \"500ms\"\nuser_agent = \"nightly-check\"\n",
+    )
+    .unwrap();
+    let config = config.path().to_str().unwrap();
+    let slow = key("deepseek", Scenario::Slow);
+    let keys = std::slice::from_ref(&slow);
+
+    let run = mock.run(keys, &["--config", config], &["deepseek"]);
+    run.assert_success();
+    assert_eq!(run.bucket("deepseek_failed_keys"), [slow.as_str()]);
+    let requests = mock.server.received_requests().await.unwrap();
+    assert_eq!(requests[0].headers["user-agent"], "nightly-check");
+
+    let run = mock.run(
+        keys,
+        &["--config", config, "--timeout", "5s"],
+        &["deepseek"],
+    );
+    run.assert_success();
+    assert_eq!(run.bucket("deepseek_key"), [slow.as_str()]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn openai_buckets_and_owner_details() {
+    let run = assert_buckets(
+        "openai",
+        &["openai"],
+        &[
+            (Scenario::Valid, "openai_key"),
+            (Scenario::ZeroBalance, "openai_no_balance_keys"),
+            (Scenario::RateLimited, "openai_429_keys"),
+            (Scenario::Unauthorized, "openai_invalid_keys"),
+            (Scenario::ServerError, "openai_server_err_keys"),
+        ],
+    )
+    .await;
+
+    let detail = run.bucket("openai_detail.csv");
+    assert!(detail[0].contains(",currency,organization,project,models,"));
+    let valid = key("openai", Scenario::Valid);
+    let valid = detail.iter().find(|row| row.starts_with(&valid)).unwrap();
+    assert!(
+        valid.contains(",org-mock,proj_mock,gpt-4o gpt-4o-mini,"),
+        "{valid}"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn anthropic_buckets() {
+    let run = assert_buckets(
+        "anthropic",
+        &["anthropic"],
+        &[
+            (Scenario::Valid, "anthropic_key"),
+            (Scenario::ZeroBalance, "anthropic_no_balance_keys"),
+            (Scenario::Disabled, "anthropic_forbidden_keys"),
+            (Scenario::Unauthorized, "anthropic_invalid_keys"),
+            (Scenario::RateLimited, "anthropic_429_keys"),
+            (Scenario::ServerError, "anthropic_server_err_keys"),
+        ],
+    )
+    .await;
+
+    let detail = run.bucket("anthropic_detail.csv");
+    let valid = key("anthropic", Scenario::Valid);
+    let valid = detail.iter().find(|row| row.starts_with(&valid)).unwrap();
+    assert!(
+        valid.contains(",org-mock,claude-sonnet-4-5 claude-haiku-4-5,"),
+        "{valid}"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn zhipu_signs_tokens() {
+    assert_buckets(
+        "zhipu",
+        &["zhipu"],
+        &[
+            (Scenario::Valid, "zhipu_key"),
+            (Scenario::NegativeBalance, "zhipu_arrears_keys"),
+            (Scenario::Unauthorized, "zhipu_invalid_keys"),
+            (Scenario::RateLimited, "zhipu_429_keys"),
+        ],
+    )
+    .await;
+
+    let mock = MockProviders::start().await;
+    let run = mock.run(&["no-separator".to_string()], &[], &["zhipu"]);
+    run.assert_success();
+    assert_eq!(run.bucket("zhipu_malformed_keys"), ["no-separator"]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn dashscope_error_codes() {
+    let scenarios = [
+        (Scenario::Valid, "dashscope_key", ""),
+        (
+            Scenario::NegativeBalance,
+            "dashscope_arrears_keys",
+            "Arrearage",
+        ),
+        (
+            Scenario::Unauthorized,
+            "dashscope_invalid_keys",
+            "invalid_api_key",
+        ),
+        (
+            Scenario::Disabled,
+            "dashscope_invalid_keys",
+            "InvalidApiKey",
+        ),
+    ];
+    let run = assert_buckets(
+        "dashscope",
+        &["dashscope"],
+        &scenarios.map(|(scenario, bucket, _)| (scenario, bucket)),
+    )
+    .await;
+
+    let detail = run.bucket("dashscope_detail.csv");
+    for (scenario, _, code) in scenarios {
+        let key = key("dashscope", scenario);
+        let row = detail.iter().find(|row| row.starts_with(&key)).unwrap();
+        assert!(row.contains(&format!(",{code},")), "{row}");
+    }
+}
@@ -0,0 +1,80 @@
+use std::path::Path;
+
+use aicheck::{
+    gemini,
+    KeyStatus::{self, *},
+};
+
+/// Recorded Gemini answers and where each one must send its key.
+const GOLDEN: &[(&str, u16, KeyStatus, &str)] = &[
+    ("ok.json", 200, Valid, "key"),
+    ("leaked.json", 403, Disabled, "gemma3_only_keys"),
+    ("proxy_403.html", 403, RegionBlocked, "location_err_key"),
+    ("service_disabled.json", 403, Invalid, "invalid_keys"),
+    ("location.json", 400, RegionBlocked, "location_err_key"),
+    ("api_key_invalid.json", 400, Invalid, "invalid_keys"),
+    ("api_key_expired.json", 400, Invalid, "invalid_keys"),
+    ("unauthenticated.json", 401, Invalid, "invalid_keys"),
+    ("quota_per_minute.json", 429, Invalid, "invalid_keys"),
+    ("resource_exhausted.json", 429, RateLimited, "429_keys"),
+    ("internal.json", 500, Unknown, "unknow_err_key"),
+    ("overloaded.json", 503, Unknown, "unknow_err_key"),
+];
+
+fn fixture(name: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/gemini")
+        .join(name);
+    std::fs::read_to_string(&path).unwrap_or_else(|err| panic!("{}: {err}", path.display()))
+}
+
+#[test]
+fn recorded_responses() {
+    for &(name, status, expected, bucket) in GOLDEN {
+        let body = fixture(name);
+        let classification = gemini::classify(status, &body);
+        assert_eq!(classification.status, expected, "{name}");
+        assert_eq!(classification.buckets, [bucket], "{name}");
+        match status {
+            200 => assert_eq!(classification.error, None, "{name}"),
+            _ => assert_eq!(
+                classification.error.as_deref(),
+                Some(body.as_str()),
+                "{name}"
+            ),
+        }
+    }
+}
+
+/// `say_hi` collapses whitespace before classifying; the rules must not
+/// depend on the original line breaks.
+#[test]
+fn collapsed_whitespace() {
+    for &(name, status, expected, bucket) in GOLDEN {
+        let body = fixture(name)
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        let classification = gemini::classify(status, &body);
+        assert_eq!(classification.status, expected, "{name}");
+        assert_eq!(classification.buckets, [bucket], "{name}");
+    }
+}
+
+/// Without `--model` only the status is known, as `list_model` drops the body.
+#[test]
+fn empty_bodies() {
+    let cases = [
+        (200, Valid, "key"),
+        (400, Invalid, "invalid_keys"),
+        (401, Invalid, "invalid_keys"),
+        (403, RegionBlocked, "location_err_key"),
+        (429, RateLimited, "429_keys"),
+        (500, Unknown, "unknow_err_key"),
+    ];
+    for (status, expected, bucket) in cases {
+        let classification = gemini::classify(status, "");
+        assert_eq!(classification.status, expected, "{status}");
+        assert_eq!(classification.buckets, [bucket], "{status}");
+    }
+}
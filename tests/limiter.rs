@@ -0,0 +1,54 @@
+use std::time::{Duration, Instant};
+
+use aicheck::{limiter::RateChange, Limits, RateLimiter};
+
+fn limits(rps: f64) -> Limits {
+    Limits {
+        rps,
+        concurrency: 1,
+        burst: 1,
+    }
+}
+
+#[test]
+#[should_panic(expected = "rps must be a positive number")]
+fn zero_rps_is_rejected() {
+    RateLimiter::new(&limits(0.0));
+}
+
+#[test]
+#[should_panic(expected = "rps must be a positive number")]
+fn negative_rps_is_rejected() {
+    RateLimiter::new(&limits(-1.0));
+}
+
+#[tokio::test]
+async fn acquire_waits_for_the_next_token() {
+    let limiter = RateLimiter::new(&limits(20.0));
+    let start = Instant::now();
+    limiter.acquire().await;
+    limiter.acquire().await;
+    assert!(start.elapsed() >= Duration::from_millis(45));
+}
+
+#[test]
+fn repeated_429s_halve_the_rate_down_to_a_floor() {
+    let limiter = RateLimiter::new(&limits(10.0));
+    let changes = (0..200)
+        .filter_map(|_| limiter.record(true))
+        .collect::<Vec<_>>();
+    let rates = [5.0, 2.5, 1.25, 0.625, 10.0 / 32.0].map(RateChange::Slowed);
+    assert_eq!(changes, rates);
+}
+
+#[test]
+fn answers_without_429s_restore_the_rate() {
+    let limiter = RateLimiter::new(&limits(10.0));
+    assert_eq!(limiter.record(false), None);
+    while limiter.record(true).is_none() {}
+
+    let changes = (0..200)
+        .filter_map(|_| limiter.record(false))
+        .collect::<Vec<_>>();
+    assert_eq!(changes, [RateChange::Restored(10.0)]);
+}
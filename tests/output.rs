@@ -0,0 +1,63 @@
+use std::time::SystemTime;
+
+use aicheck::{
+    deepseek::Deepseek,
+    output::{create_run_folder, run_folder_name},
+    BucketSink, Provider, ReportSink,
+};
+
+#[tokio::test]
+async fn runs_in_the_same_second_get_their_own_folder() {
+    let dir = tempfile::tempdir().unwrap();
+    let now = SystemTime::now();
+    let name = run_folder_name(now);
+
+    let first = create_run_folder(dir.path(), now).await.unwrap();
+    let second = create_run_folder(dir.path(), now).await.unwrap();
+    let third = create_run_folder(dir.path(), now).await.unwrap();
+
+    assert_eq!(first, dir.path().join(&name));
+    assert_eq!(second, dir.path().join(format!("{name}-2")));
+    assert_eq!(third, dir.path().join(format!("{name}-3")));
+}
+
+fn file_names(dir: &std::path::Path) -> Vec<String> {
+    let mut names = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect::<Vec<_>>();
+    names.sort();
+    names
+}
+
+#[tokio::test]
+async fn unfinished_runs_leave_no_temp_files() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("deepseek_key"), "previous\n").unwrap();
+    let deepseek = Deepseek::default();
+
+    let first = BucketSink::create([&deepseek as &dyn Provider], dir.path())
+        .await
+        .unwrap();
+    let mut second = BucketSink::create([&deepseek as &dyn Provider], dir.path())
+        .await
+        .unwrap();
+    drop(first);
+    assert_eq!(
+        file_names(dir.path()).len(),
+        1 + deepseek.buckets().len() + 1
+    );
+
+    second.finish().await.unwrap();
+    drop(second);
+    let names = file_names(dir.path());
+    assert!(
+        names.iter().all(|name| !name.ends_with(".tmp")),
+        "{names:?}"
+    );
+    assert_eq!(names.len(), deepseek.buckets().len() + 1);
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join("deepseek_key")).unwrap(),
+        ""
+    );
+}
@@ -0,0 +1,104 @@
+use std::time::{Duration, SystemTime};
+
+use aicheck::{
+    provider::Response,
+    retry::{retry_after, RetryPolicy},
+    ProbeError,
+};
+use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+
+fn answer(status: u16, retry_after: Option<&str>) -> Result<Response, ProbeError> {
+    let mut headers = HeaderMap::new();
+    if let Some(value) = retry_after {
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(value).unwrap());
+    }
+    Ok(Response {
+        status,
+        headers,
+        text: String::new(),
+    })
+}
+
+fn headers(retry_after: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(RETRY_AFTER, HeaderValue::from_str(retry_after).unwrap());
+    headers
+}
+
+#[test]
+fn transport_errors_429_and_5xx_are_retried() {
+    let policy = RetryPolicy::default();
+    let transport = Err(ProbeError::Transport("connection reset".to_string()));
+    for resp in [
+        transport,
+        answer(429, None),
+        answer(500, None),
+        answer(503, None),
+    ] {
+        let wait = policy.delay(1, &resp).expect("retried");
+        assert!(wait <= policy.base_delay);
+    }
+}
+
+#[test]
+fn other_answers_are_final() {
+    let policy = RetryPolicy::default();
+    for status in [200, 400, 401, 403, 404] {
+        assert_eq!(policy.delay(1, &answer(status, None)), None, "{status}");
+    }
+}
+
+#[test]
+fn gives_up_after_max_attempts() {
+    let policy = RetryPolicy {
+        max_attempts: 2,
+        ..Default::default()
+    };
+    assert!(policy.delay(1, &answer(503, None)).is_some());
+    assert_eq!(policy.delay(2, &answer(503, None)), None);
+}
+
+#[test]
+fn backoff_stays_below_max_delay() {
+    let policy = RetryPolicy {
+        max_attempts: 100,
+        ..Default::default()
+    };
+    for attempt in 1..100 {
+        let wait = policy.delay(attempt, &answer(503, None)).unwrap();
+        assert!(wait <= policy.max_delay, "{attempt}: {wait:?}");
+    }
+}
+
+#[test]
+fn retry_after_seconds() {
+    assert_eq!(retry_after(&headers("2")), Some(Duration::from_secs(2)));
+    assert_eq!(retry_after(&headers(" 0 ")), Some(Duration::ZERO));
+    assert_eq!(retry_after(&headers("soon")), None);
+    assert_eq!(retry_after(&HeaderMap::new()), None);
+}
+
+#[test]
+fn retry_after_http_date() {
+    let later = SystemTime::now() + Duration::from_secs(10);
+    let wait = retry_after(&headers(&httpdate::fmt_http_date(later))).unwrap();
+    assert!(wait > Duration::from_secs(8) && wait <= Duration::from_secs(10));
+
+    let earlier = SystemTime::now() - Duration::from_secs(10);
+    let wait = retry_after(&headers(&httpdate::fmt_http_date(earlier)));
+    assert_eq!(wait, Some(Duration::ZERO));
+}
+
+#[test]
+fn server_wait_is_honoured_up_to_max_delay() {
+    let policy = RetryPolicy::default();
+    assert_eq!(
+        policy.delay(1, &answer(429, Some("3"))),
+        Some(Duration::from_secs(3))
+    );
+    assert_eq!(
+        policy.delay(1, &answer(503, Some("30"))),
+        Some(Duration::from_secs(30))
+    );
+    assert_eq!(policy.delay(1, &answer(429, Some("31"))), None);
+}